@@ -0,0 +1,57 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use icmpp::{Icmp, SocketKind};
+use socket2::SockAddr;
+
+const PACKETS: usize = 64;
+
+fn loopback() -> Icmp {
+    let mut icmp = Icmp::from_ip(SocketKind::Auto, Ipv4Addr::LOCALHOST.into(), 0x4242, None)
+        .expect("needs CAP_NET_RAW or ping_group_range");
+    icmp.set_timeout(Some(Duration::from_secs(1)));
+    icmp
+}
+
+fn drain(icmp: &Icmp) {
+    while icmp.recv_timeout(Duration::from_millis(10)).is_ok() {}
+}
+
+fn send_recv(c: &mut Criterion) {
+    let mut icmp = loopback();
+    let dsts = vec![SockAddr::from(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))); PACKETS];
+
+    let mut group = c.benchmark_group("loopback");
+    group.throughput(Throughput::Elements(PACKETS as u64));
+
+    group.bench_function("send_recv", |b| {
+        drain(&icmp);
+        b.iter(|| {
+            for _ in 0..PACKETS {
+                icmp.send().unwrap();
+            }
+            for _ in 0..PACKETS {
+                icmp.recv().unwrap();
+            }
+        })
+    });
+
+    group.bench_function("send_recv_batch", |b| {
+        drain(&icmp);
+        b.iter(|| {
+            assert_eq!(icmp.send_batch(&dsts).unwrap(), PACKETS);
+            let mut received = 0;
+            while received < PACKETS {
+                received += icmp.recv_batch(PACKETS - received).unwrap().len();
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, send_recv);
+criterion_main!(benches);
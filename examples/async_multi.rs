@@ -0,0 +1,30 @@
+use std::{io, time::Duration};
+
+use icmpp::{AsyncMultiPinger, MultiPinger, SocketKind, Version};
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let mut pinger = MultiPinger::new(SocketKind::Auto, Version::V4, 100, None)?
+        .count(4)
+        .interval(Duration::from_secs(1))
+        .timeout(Duration::from_secs(1));
+    for host in ["127.0.0.1", "127.0.0.2", "127.0.0.3"] {
+        pinger.add_target(host)?;
+    }
+
+    let mut pinger = AsyncMultiPinger::new(pinger)?;
+    pinger
+        .run(|i, reply| {
+            println!(
+                "target {}: icmp_seq={} rtt={:?}",
+                i,
+                reply.resp.sequence(),
+                reply.rtt
+            )
+        })
+        .await?;
+    for target in pinger.get_ref().targets() {
+        println!("{}", target);
+    }
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::{io, time::Duration};
+
+use icmpp::{AsyncIcmp, Icmp, Version};
+use tokio::time::{sleep, timeout};
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let hosts = ["127.0.0.1", "::1"];
+    let tasks = hosts.iter().enumerate().map(|(i, &host)| {
+        tokio::spawn(async move {
+            let ver = if host.contains(':') {
+                Version::V6
+            } else {
+                Version::V4
+            };
+            let mut icmp = Icmp::new(ver, host, 100 + i as u16, None)?;
+            icmp.set_timestamp(true)?;
+            let mut icmp = AsyncIcmp::new(icmp)?;
+            for _ in 0..4 {
+                icmp.send().await?;
+                match timeout(Duration::from_secs(1), icmp.recv()).await {
+                    Ok(res) => {
+                        let (_, _, resp) = res?;
+                        println!(
+                            "{}: icmp_seq={} ttl={} rtt={:?}",
+                            host,
+                            resp.sequence(),
+                            resp.ttl(),
+                            resp.rtt()
+                        );
+                    }
+                    Err(_) => println!("{}: request timeout", host),
+                }
+                sleep(Duration::from_secs(1)).await;
+            }
+            Ok::<_, io::Error>(())
+        })
+    });
+    for task in tasks.collect::<Vec<_>>() {
+        task.await
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+    }
+    Ok(())
+}
@@ -1,20 +1,26 @@
-use std::{thread::sleep, time::Duration};
+use std::time::Duration;
 
-use icmpp::{Icmp, Version};
+use icmpp::{Icmp, Pinger, Version};
 
 fn main() {
     let mut icmp = Icmp::new(Version::V4, "www.baidu.com", 99, None).unwrap();
-    for _ in 0..8 {
-        icmp.send().unwrap();
-        let (len, addr, resp) = icmp.recv().unwrap();
-        println!(
-            "total len: {}, packet len: {}, recv from: {:?}, resp: {:02x?}",
-            len,
-            resp.len(),
-            addr.as_socket_ipv4(),
-            resp
-        );
+    icmp.set_timestamp(true).unwrap();
 
-        sleep(Duration::from_secs(1));
-    }
+    let mut pinger = Pinger::new(icmp)
+        .count(Some(8))
+        .interval(Duration::from_secs(1))
+        .timeout(Duration::from_secs(1));
+    let summary = pinger
+        .run(|reply| {
+            println!(
+                "total len: {}, packet len: {}, recv from: {:?}, rtt: {:?}, resp: {:02x?}",
+                reply.len,
+                reply.resp.len(),
+                reply.addr.as_socket_ipv4(),
+                reply.rtt,
+                reply.resp
+            )
+        })
+        .unwrap();
+    println!("{}", summary);
 }
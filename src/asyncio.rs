@@ -0,0 +1,126 @@
+use std::{io, time::Instant};
+
+use socket2::SockAddr;
+use tokio::{io::unix::AsyncFd, time::timeout_at};
+
+use crate::{Icmp, MultiPinger, Reply, Response};
+
+/// An `Icmp` driven by the tokio reactor. The socket is switched to
+/// non-blocking mode and the default receive timeout of the wrapped `Icmp` is
+/// not used; wrap `recv` in `tokio::time::timeout` instead.
+///
+/// Every `AsyncIcmp` on a raw socket is woken by the replies to all of them,
+/// so use an `AsyncMultiPinger` to ping many destinations.
+#[derive(Debug)]
+pub struct AsyncIcmp {
+    inner: AsyncFd<Icmp>,
+}
+
+impl AsyncIcmp {
+    pub fn new(icmp: Icmp) -> io::Result<Self> {
+        icmp.sock.set_nonblocking(true)?;
+        Ok(Self {
+            inner: AsyncFd::new(icmp)?,
+        })
+    }
+
+    /// Sends an echo request, returning the sequence number it carried.
+    pub async fn send(&mut self) -> io::Result<u16> {
+        loop {
+            let mut guard = self.inner.writable_mut().await?;
+            match guard.try_io(|inner| inner.get_mut().send()) {
+                Ok(res) => return res,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    pub async fn recv(&self) -> io::Result<(usize, SockAddr, Response)> {
+        loop {
+            let mut guard = self.inner.readable().await?;
+            match guard.try_io(|inner| inner.get_ref().recv_deadline(None)) {
+                Ok(res) => return res,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &Icmp {
+        self.inner.get_ref()
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut Icmp {
+        self.inner.get_mut()
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> Icmp {
+        self.inner.into_inner()
+    }
+}
+
+/// A `MultiPinger` driven by the tokio reactor, pinging all its targets over
+/// one non-blocking socket.
+#[derive(Debug)]
+pub struct AsyncMultiPinger {
+    inner: AsyncFd<MultiPinger>,
+}
+
+impl AsyncMultiPinger {
+    pub fn new(mut pinger: MultiPinger) -> io::Result<Self> {
+        pinger.icmp_mut().sock.set_nonblocking(true)?;
+        Ok(Self {
+            inner: AsyncFd::new(pinger)?,
+        })
+    }
+
+    /// Runs all rounds as `MultiPinger::run` does, calling `on_reply` with the
+    /// target index of every reply.
+    pub async fn run<F: FnMut(usize, &Reply)>(&mut self, mut on_reply: F) -> io::Result<()> {
+        let (count, interval, wait) = {
+            let pinger = self.inner.get_ref();
+            (pinger.count, pinger.interval, pinger.timeout)
+        };
+        let start = Instant::now();
+        let mut next = start;
+
+        for round in 0..count {
+            let mut guard = self.inner.writable_mut().await?;
+            guard.get_inner_mut().send_round(start);
+            drop(guard);
+            next += interval;
+            let last = round + 1 == count;
+            let until = if last { Instant::now() + wait } else { next };
+
+            while !(last && self.inner.get_ref().all_acked()) {
+                let readable = timeout_at(until.into(), self.inner.readable_mut());
+                let mut guard = match readable.await {
+                    Ok(guard) => guard?,
+                    Err(_elapsed) => break,
+                };
+                match guard.try_io(|inner| inner.get_mut().recv_round(None, &mut on_reply)) {
+                    Ok(res) => res?,
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &MultiPinger {
+        self.inner.get_ref()
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut MultiPinger {
+        self.inner.get_mut()
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> MultiPinger {
+        self.inner.into_inner()
+    }
+}
@@ -0,0 +1,82 @@
+use std::{convert::TryInto, net::Ipv6Addr};
+
+/// Computes the Internet checksum of RFC 1071. An odd trailing byte is padded
+/// with zero; the checksum field itself must be zero when computing and left
+/// in place when verifying, in which case the result is 0.
+pub fn checksum(bytes: &[u8]) -> u16 {
+    !fold(sum(bytes))
+}
+
+/// Computes the ICMPv6 checksum, which also covers a pseudo-header made of
+/// the source and destination addresses, the message length and the next
+/// header value (RFC 8200, section 8.1).
+pub fn checksum_v6(src: &Ipv6Addr, dst: &Ipv6Addr, bytes: &[u8]) -> u16 {
+    let pseudo = sum(&src.octets())
+        + sum(&dst.octets())
+        + sum(&(bytes.len() as u32).to_be_bytes())
+        + libc::IPPROTO_ICMPV6 as u64;
+    !fold(pseudo + sum(bytes))
+}
+
+/// Updates checksum `sum` after a 16-bit word of the covered data changed
+/// from `old` to `new`, without summing the data again (RFC 1624, eqn. 3).
+pub fn checksum_update(sum: u16, old: u16, new: u16) -> u16 {
+    !fold(!sum as u64 + !old as u64 + new as u64)
+}
+
+fn sum(bytes: &[u8]) -> u64 {
+    let mut chunks = bytes.chunks_exact(2);
+    let mut sum = (&mut chunks)
+        .map(|buf| u16::from_be_bytes(buf.try_into().unwrap()) as u64)
+        .sum::<u64>();
+    if let [b] = chunks.remainder() {
+        sum += (*b as u64) << 8;
+    }
+    sum
+}
+
+fn fold(mut sum: u64) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
+    sum as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc1071_example() {
+        let bytes = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(checksum(&bytes), !0xddf2);
+    }
+
+    #[test]
+    fn odd_length() {
+        let mut bytes = vec![8, 0, 0, 0, 0, 99, 0, 1, 0xab];
+        let sum = checksum(&bytes);
+        assert_eq!(sum, checksum(&[8, 0, 0, 0, 0, 99, 0, 1, 0xab, 0]));
+        bytes[2..4].copy_from_slice(&sum.to_be_bytes());
+        assert_eq!(checksum(&bytes), 0);
+    }
+
+    #[test]
+    fn incremental_update() {
+        let mut bytes = vec![8, 0, 0, 0, 0, 99, 0, 1, 1, 2, 3];
+        let sum = checksum(&bytes);
+        bytes[6..8].copy_from_slice(&2u16.to_be_bytes());
+        assert_eq!(checksum_update(sum, 1, 2), checksum(&bytes));
+    }
+
+    #[test]
+    fn pseudo_header() {
+        let (src, dst) = (Ipv6Addr::LOCALHOST, "fe80::1".parse().unwrap());
+        let mut bytes = vec![128, 0, 0, 0, 0, 99, 0, 1, 7];
+        let sum = checksum_v6(&src, &dst, &bytes);
+        bytes[2..4].copy_from_slice(&sum.to_be_bytes());
+        assert_eq!(checksum_v6(&src, &dst, &bytes), 0);
+        assert_eq!(checksum_v6(&dst, &src, &bytes), 0);
+        assert_ne!(checksum(&bytes), 0);
+    }
+}
@@ -0,0 +1,98 @@
+use std::{error::Error, fmt, io};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    Truncated,
+    BadIhl(u8),
+    BadChecksum,
+    UnsupportedVersion(u8),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "truncated packet"),
+            DecodeError::BadIhl(ihl) => write!(f, "bad IP header length: {}", ihl),
+            DecodeError::BadChecksum => write!(f, "bad ICMP checksum"),
+            DecodeError::UnsupportedVersion(ver) => write!(f, "unsupported IP version: {}", ver),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+impl From<DecodeError> for io::Error {
+    fn from(err: DecodeError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// How the data of an echo reply differs from what was sent, see
+/// `Icmp::verify_data`. Offsets count from the start of the data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataError {
+    Truncated {
+        len: usize,
+        expected: usize,
+    },
+    Mismatch {
+        offset: usize,
+        expected: u8,
+        found: u8,
+    },
+}
+
+/// Formats the complaint of iputils ping.
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataError::Truncated { len, expected } => {
+                write!(f, "truncated data: {} of {} bytes", len, expected)
+            }
+            DataError::Mismatch {
+                offset,
+                expected,
+                found,
+            } => write!(
+                f,
+                "wrong data byte #{} should be 0x{:x} but was 0x{:x}",
+                offset, expected, found
+            ),
+        }
+    }
+}
+
+impl Error for DataError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    /// The host name contains a NUL byte.
+    InvalidHost,
+    /// `getaddrinfo` failed; `msg` is the text of `gai_strerror`.
+    Lookup { code: i32, msg: String },
+    /// The name resolved, but not to an address of the requested family.
+    NoAddress,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::InvalidHost => write!(f, "invalid host name"),
+            ResolveError::Lookup { msg, .. } => write!(f, "failed to resolve host: {}", msg),
+            ResolveError::NoAddress => write!(f, "no address of the requested family"),
+        }
+    }
+}
+
+impl Error for ResolveError {}
+
+impl From<ResolveError> for io::Error {
+    fn from(err: ResolveError) -> Self {
+        let kind = match err {
+            ResolveError::InvalidHost => io::ErrorKind::InvalidInput,
+            ResolveError::Lookup { .. } => io::ErrorKind::Other,
+            ResolveError::NoAddress => io::ErrorKind::NotFound,
+        };
+        io::Error::new(kind, err)
+    }
+}
@@ -0,0 +1,279 @@
+use std::{io, net::IpAddr};
+
+use libc::{
+    sock_filter, BPF_ABS, BPF_B, BPF_H, BPF_IND, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_LDX, BPF_MSH,
+    BPF_RET, BPF_W, SKF_NET_OFF,
+};
+use socket2::Socket;
+
+use crate::{sys, Version};
+
+/// `ICMP_FILTER` of `SOL_RAW` and `ICMP6_FILTER` of `IPPROTO_ICMPV6`, missing
+/// from libc.
+const ICMP_FILTER: libc::c_int = 1;
+const ICMP6_FILTER: libc::c_int = 1;
+
+/// A set of ICMP types a raw socket passes to userspace, installed with
+/// `Icmp::set_type_filter`. The kernel drops the others before they are
+/// queued. The IPv4 filter only covers types below 32, so higher types
+/// always pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeFilter {
+    pass: [u32; 8],
+}
+
+impl TypeFilter {
+    /// A filter passing nothing, to add types to with `pass`.
+    pub fn none() -> Self {
+        Self { pass: [0; 8] }
+    }
+
+    /// A filter passing everything, to remove types from with `block`.
+    pub fn all() -> Self {
+        Self { pass: [!0; 8] }
+    }
+
+    /// A filter passing only echo replies.
+    pub fn echo_reply(ver: Version) -> Self {
+        Self::none().pass(ver.echo_reply())
+    }
+
+    pub fn pass(mut self, typ: u8) -> Self {
+        self.pass[typ as usize / 32] |= 1 << (typ % 32);
+        self
+    }
+
+    pub fn block(mut self, typ: u8) -> Self {
+        self.pass[typ as usize / 32] &= !(1 << (typ % 32));
+        self
+    }
+
+    #[inline]
+    pub fn passes(&self, typ: u8) -> bool {
+        self.pass[typ as usize / 32] & (1 << (typ % 32)) != 0
+    }
+
+    /// Installs the filter; the kernel takes a bitmask of blocked types.
+    pub(crate) fn install(&self, sock: &Socket, ver: Version) -> io::Result<()> {
+        match ver {
+            Version::V4 => sys::setsockopt(sock, libc::SOL_RAW, ICMP_FILTER, !self.pass[0]),
+            Version::V6 => {
+                let block = self.pass.map(|word| !word);
+                sys::setsockopt(sock, libc::IPPROTO_ICMPV6, ICMP6_FILTER, block)
+            }
+        }
+    }
+}
+
+/// Where a conditional jump of `reply_program` goes.
+#[derive(Clone, Copy)]
+enum Jump {
+    Next,
+    Pass,
+    Reject,
+}
+
+/// Builds a classic BPF program for a raw socket that rejects echo replies
+/// not carrying `idt`, or not coming from `src` if given, and passes all other
+/// messages, such as the errors traceroute relies on. IPv4 raw sockets see the
+/// IP header first; IPv6 ones start at the ICMPv6 header, so the source
+/// address is loaded relative to the network header instead.
+pub(crate) fn reply_program(ver: Version, idt: u16, src: Option<IpAddr>) -> Vec<sock_filter> {
+    let mut prog = Vec::new();
+    let mut insn = |code: u32, k: u32, jt: Jump, jf: Jump| prog.push((code, k, jt, jf));
+    let ld = |size: u32, mode: u32| BPF_LD | size | mode;
+    let jeq = BPF_JMP | BPF_JEQ | BPF_K;
+
+    let mode = match ver {
+        Version::V4 => {
+            // X = IP header length.
+            insn(BPF_LDX | BPF_B | BPF_MSH, 0, Jump::Next, Jump::Next);
+            BPF_IND
+        }
+        Version::V6 => BPF_ABS,
+    };
+    insn(ld(BPF_B, mode), 0, Jump::Next, Jump::Next);
+    insn(jeq, ver.echo_reply() as u32, Jump::Next, Jump::Pass);
+    insn(ld(BPF_H, mode), 4, Jump::Next, Jump::Next);
+    insn(jeq, idt as u32, Jump::Next, Jump::Reject);
+    match src {
+        Some(IpAddr::V4(src)) => {
+            insn(ld(BPF_W, BPF_ABS), 12, Jump::Next, Jump::Next);
+            insn(jeq, u32::from(src), Jump::Next, Jump::Reject);
+        }
+        Some(IpAddr::V6(src)) => {
+            for (i, word) in src.octets().chunks_exact(4).enumerate() {
+                let off = SKF_NET_OFF as u32 + 8 + 4 * i as u32;
+                let word = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+                insn(ld(BPF_W, BPF_ABS), off, Jump::Next, Jump::Next);
+                insn(jeq, word, Jump::Next, Jump::Reject);
+            }
+        }
+        None => {}
+    }
+
+    let pass = prog.len();
+    let reject = pass + 1;
+    let mut filter: Vec<_> = prog
+        .into_iter()
+        .enumerate()
+        .map(|(i, (code, k, jt, jf))| {
+            let off = |jump| match jump {
+                Jump::Next => 0,
+                Jump::Pass => (pass - i - 1) as u8,
+                Jump::Reject => (reject - i - 1) as u8,
+            };
+            sock_filter {
+                code: code as u16,
+                jt: off(jt),
+                jf: off(jf),
+                k,
+            }
+        })
+        .collect();
+    for k in [!0, 0] {
+        filter.push(sock_filter {
+            code: (BPF_RET | BPF_K) as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        });
+    }
+    filter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_bits() {
+        let filter = TypeFilter::echo_reply(Version::V6).pass(1);
+        assert!(filter.passes(129) && filter.passes(1));
+        assert!(!filter.passes(128) && !filter.passes(0));
+        assert_eq!(filter.block(129), TypeFilter::none().pass(1));
+        assert!(!TypeFilter::all().block(255).passes(255));
+    }
+
+    /// Runs a program the way the kernel does on `pkt`, which starts at the
+    /// network header and has the socket data begin at `data`. Loads past
+    /// the end reject, as they do in the kernel.
+    fn eval(prog: &[sock_filter], pkt: &[u8], data: usize) -> u32 {
+        let (mut a, mut x, mut pc) = (0u32, 0u32, 0);
+        let at = |off: usize, size: usize| {
+            let bytes = pkt.get(off..off + size)?;
+            Some(bytes.iter().fold(0, |acc, &b| acc << 8 | b as u32))
+        };
+        loop {
+            let insn = prog[pc];
+            let code = insn.code as u32;
+            pc += 1;
+            if code == BPF_RET | BPF_K {
+                return insn.k;
+            } else if code == BPF_JMP | BPF_JEQ | BPF_K {
+                pc += if a == insn.k { insn.jt } else { insn.jf } as usize;
+            } else if code == BPF_LDX | BPF_B | BPF_MSH {
+                match at(data + insn.k as usize, 1) {
+                    Some(b) => x = (b & 0xf) * 4,
+                    None => return 0,
+                }
+            } else {
+                let size = match code & 0x18 {
+                    BPF_W => 4,
+                    BPF_H => 2,
+                    BPF_B => 1,
+                    _ => unreachable!(),
+                };
+                let off = match code & 0xe0 {
+                    BPF_ABS if (insn.k as i32) < 0 => insn.k.wrapping_sub(SKF_NET_OFF as u32),
+                    BPF_ABS => (data as u32) + insn.k,
+                    BPF_IND => (data as u32) + x + insn.k,
+                    _ => unreachable!(),
+                };
+                match at(off as usize, size) {
+                    Some(val) => a = val,
+                    None => return 0,
+                }
+            }
+        }
+    }
+
+    /// An IPv4 packet from `src`, with `opts` words of options, carrying an
+    /// ICMP message of type `typ` and identifier `idt`.
+    fn v4(src: [u8; 4], opts: u8, typ: u8, idt: u16) -> Vec<u8> {
+        let mut pkt = vec![0x45 + opts, 0, 0, 0, 0, 0, 0, 0, 64, 1, 0, 0];
+        pkt.extend_from_slice(&src);
+        pkt.extend_from_slice(&[10, 0, 0, 2]);
+        pkt.resize(pkt.len() + 4 * opts as usize, 1);
+        pkt.extend_from_slice(&[typ, 0, 0, 0]);
+        pkt.extend_from_slice(&idt.to_be_bytes());
+        pkt.extend_from_slice(&[0, 1]);
+        pkt
+    }
+
+    /// The IPv6 counterpart of `v4`, without extension headers.
+    fn v6(src: &str, typ: u8, idt: u16) -> Vec<u8> {
+        let src: std::net::Ipv6Addr = src.parse().unwrap();
+        let mut pkt = vec![0x60, 0, 0, 0, 0, 8, 58, 64];
+        pkt.extend_from_slice(&src.octets());
+        pkt.extend_from_slice(&"fd00::2".parse::<std::net::Ipv6Addr>().unwrap().octets());
+        pkt.extend_from_slice(&[typ, 0, 0, 0]);
+        pkt.extend_from_slice(&idt.to_be_bytes());
+        pkt.extend_from_slice(&[0, 1]);
+        pkt
+    }
+
+    #[test]
+    fn reply_program_jumps() {
+        for (ver, src) in [
+            (Version::V4, None),
+            (Version::V4, Some("10.0.0.1".parse().unwrap())),
+            (Version::V6, Some("fd00::1".parse().unwrap())),
+        ] {
+            let prog = reply_program(ver, 77, src);
+            let len = prog.len();
+            assert_eq!(prog[len - 2].k, !0);
+            assert_eq!(prog[len - 1].k, 0);
+            for (i, insn) in prog[..len - 2].iter().enumerate() {
+                assert!(i + 1 + (insn.jt.max(insn.jf) as usize) < len);
+            }
+            assert!(prog.iter().any(|insn| insn.k == 77));
+        }
+    }
+
+    #[test]
+    fn reply_program_v4() {
+        let ours = [10, 0, 0, 1];
+        let any = reply_program(Version::V4, 77, None);
+        let from = reply_program(Version::V4, 77, Some(IpAddr::from(ours)));
+        for opts in [0, 2] {
+            let pass = |prog: &[_], pkt: Vec<u8>| eval(prog, &pkt, 0) != 0;
+
+            assert!(pass(&any, v4([192, 0, 2, 9], opts, 0, 77)));
+            assert!(!pass(&any, v4(ours, opts, 0, 78)));
+            assert!(pass(&from, v4(ours, opts, 0, 77)));
+            assert!(!pass(&from, v4([10, 0, 0, 9], opts, 0, 77)));
+            assert!(!pass(&from, v4(ours, opts, 0, 78)));
+            // Errors and requests pass whatever they carry.
+            assert!(pass(&from, v4([192, 0, 2, 9], opts, 11, 0)));
+            assert!(pass(&from, v4([192, 0, 2, 9], opts, 8, 78)));
+        }
+    }
+
+    #[test]
+    fn reply_program_v6() {
+        let any = reply_program(Version::V6, 77, None);
+        let from = reply_program(Version::V6, 77, Some("fd00::1".parse().unwrap()));
+        // Raw IPv6 sockets see the packet from the ICMPv6 header on.
+        let pass = |prog: &[_], pkt: Vec<u8>| eval(prog, &pkt, 40) != 0;
+
+        assert!(pass(&any, v6("fd00::9", 129, 77)));
+        assert!(!pass(&any, v6("fd00::1", 129, 78)));
+        assert!(pass(&from, v6("fd00::1", 129, 77)));
+        assert!(!pass(&from, v6("fd00::9", 129, 77)));
+        assert!(!pass(&from, v6("fe00::1", 129, 77)));
+        assert!(!pass(&from, v6("fd00::1", 129, 78)));
+        assert!(pass(&from, v6("fd00::9", 3, 0)));
+        assert!(pass(&from, v6("fd00::9", 128, 78)));
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    convert::TryInto,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use crate::DecodeError;
+
+const IPOPT_EOL: u8 = 0;
+const IPOPT_NOP: u8 = 1;
+const IPOPT_RR: u8 = 7;
+const IPOPT_TS: u8 = 68;
+
+/// The IP header a message arrived with, see `Response::ip_header`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpHeader {
+    V4(Ipv4Header),
+    V6(Ipv6Header),
+}
+
+impl IpHeader {
+    /// Differentiated Services Code Point, the upper six bits of the TOS or
+    /// traffic class.
+    #[inline]
+    pub fn dscp(&self) -> u8 {
+        match self {
+            IpHeader::V4(hdr) => hdr.dscp,
+            IpHeader::V6(hdr) => hdr.dscp,
+        }
+    }
+
+    /// Explicit Congestion Notification, the lower two bits of the TOS or
+    /// traffic class.
+    #[inline]
+    pub fn ecn(&self) -> u8 {
+        match self {
+            IpHeader::V4(hdr) => hdr.ecn,
+            IpHeader::V6(hdr) => hdr.ecn,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv4Header {
+    pub dscp: u8,
+    pub ecn: u8,
+    pub total_len: u16,
+    pub id: u16,
+    /// The three flag bits: reserved, Don't Fragment and More Fragments.
+    pub flags: u8,
+    /// Fragment offset in units of 8 bytes.
+    pub frag_offset: u16,
+    pub ttl: u8,
+    pub protocol: u8,
+    pub checksum: u16,
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    pub options: Vec<Ipv4Option>,
+}
+
+impl Ipv4Header {
+    /// Parses the header at the start of `bytes`. Parsing of options stops at
+    /// the first malformed one.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() < 20 {
+            return Err(DecodeError::Truncated);
+        }
+        if bytes[0] >> 4 != 4 {
+            return Err(DecodeError::UnsupportedVersion(bytes[0] >> 4));
+        }
+        let ihl = bytes[0] & 0xf;
+        if ihl < 5 {
+            return Err(DecodeError::BadIhl(ihl));
+        }
+        let opts = bytes
+            .get(20..4 * ihl as usize)
+            .ok_or(DecodeError::Truncated)?;
+        let word = |i: usize| u16::from_be_bytes(bytes[i..i + 2].try_into().unwrap());
+        let addr = |i: usize| -> Ipv4Addr {
+            let octets: [u8; 4] = bytes[i..i + 4].try_into().unwrap();
+            octets.into()
+        };
+
+        Ok(Self {
+            dscp: bytes[1] >> 2,
+            ecn: bytes[1] & 0x3,
+            total_len: word(2),
+            id: word(4),
+            flags: bytes[6] >> 5,
+            frag_offset: word(6) & 0x1fff,
+            ttl: bytes[8],
+            protocol: bytes[9],
+            checksum: word(10),
+            src: addr(12),
+            dst: addr(16),
+            options: Ipv4Option::parse_all(opts),
+        })
+    }
+
+    #[inline]
+    pub fn dont_fragment(&self) -> bool {
+        self.flags & 0b010 != 0
+    }
+
+    #[inline]
+    pub fn more_fragments(&self) -> bool {
+        self.flags & 0b001 != 0
+    }
+}
+
+/// An IPv4 option (RFC 791). No-operation and End of Option List are left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv4Option {
+    /// The addresses recorded so far.
+    RecordRoute {
+        route: Vec<Ipv4Addr>,
+    },
+    /// The entries recorded so far. `overflow` counts the hops that could
+    /// not record one for lack of space. Addresses are absent with flag 0,
+    /// which records timestamps only.
+    Timestamp {
+        overflow: u8,
+        flag: u8,
+        entries: Vec<(Option<Ipv4Addr>, u32)>,
+    },
+    Other {
+        typ: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl Ipv4Option {
+    fn parse_all(mut opts: &[u8]) -> Vec<Self> {
+        let mut parsed = Vec::new();
+        while let Some(&typ) = opts.first() {
+            match typ {
+                IPOPT_EOL => break,
+                IPOPT_NOP => {
+                    opts = &opts[1..];
+                    continue;
+                }
+                _ => {}
+            }
+            let len = match opts.get(1) {
+                Some(&len) if len >= 2 && len as usize <= opts.len() => len as usize,
+                _ => break,
+            };
+            match Self::parse(typ, &opts[2..len]) {
+                Some(opt) => parsed.push(opt),
+                None => break,
+            }
+            opts = &opts[len..];
+        }
+        parsed
+    }
+
+    /// Parses the data of an option, following its type and length bytes.
+    fn parse(typ: u8, data: &[u8]) -> Option<Self> {
+        let addr = |b: &[u8]| -> Ipv4Addr {
+            let octets: [u8; 4] = b.try_into().unwrap();
+            octets.into()
+        };
+        match typ {
+            IPOPT_RR => {
+                // The pointer counts from the type byte and starts at 4.
+                let used = (*data.first()? as usize)
+                    .checked_sub(4)?
+                    .min(data.len() - 1);
+                let route = data[1..1 + used].chunks_exact(4).map(addr).collect();
+                Some(Ipv4Option::RecordRoute { route })
+            }
+            IPOPT_TS => {
+                // The pointer starts at 5, after the overflow and flag byte.
+                let used = (*data.first()? as usize).checked_sub(5)?;
+                let (overflow, flag) = (data.get(1)? >> 4, data[1] & 0xf);
+                let recs = &data[2..2 + used.min(data.len() - 2)];
+                let entries = match flag {
+                    0 => recs
+                        .chunks_exact(4)
+                        .map(|b| (None, u32::from_be_bytes(b.try_into().unwrap())))
+                        .collect(),
+                    _ => recs
+                        .chunks_exact(8)
+                        .map(|b| {
+                            let ts = u32::from_be_bytes(b[4..].try_into().unwrap());
+                            (Some(addr(&b[..4])), ts)
+                        })
+                        .collect(),
+                };
+                Some(Ipv4Option::Timestamp {
+                    overflow,
+                    flag,
+                    entries,
+                })
+            }
+            _ => Some(Ipv4Option::Other {
+                typ,
+                data: data.to_vec(),
+            }),
+        }
+    }
+}
+
+/// An IPv6 header. Raw ICMPv6 sockets do not deliver the header itself, so for
+/// received messages it is rebuilt from the source address and ancillary
+/// data, and `flow_label` is always 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv6Header {
+    pub dscp: u8,
+    pub ecn: u8,
+    pub flow_label: u32,
+    pub payload_len: u16,
+    pub next_header: u8,
+    pub hop_limit: u8,
+    pub src: Ipv6Addr,
+    pub dst: Ipv6Addr,
+}
+
+impl Ipv6Header {
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = bytes.get(..40).ok_or(DecodeError::Truncated)?;
+        if bytes[0] >> 4 != 6 {
+            return Err(DecodeError::UnsupportedVersion(bytes[0] >> 4));
+        }
+        let first = u32::from_be_bytes(bytes[..4].try_into().unwrap());
+        let tclass = (first >> 20) as u8;
+        let addr = |i: usize| -> Ipv6Addr {
+            let octets: [u8; 16] = bytes[i..i + 16].try_into().unwrap();
+            octets.into()
+        };
+
+        Ok(Self {
+            dscp: tclass >> 2,
+            ecn: tclass & 0x3,
+            flow_label: first & 0xf_ffff,
+            payload_len: u16::from_be_bytes(bytes[4..6].try_into().unwrap()),
+            next_header: bytes[6],
+            hop_limit: bytes[7],
+            src: addr(8),
+            dst: addr(24),
+        })
+    }
+
+    /// Serializes a header without extension headers.
+    pub(crate) fn synthesize(
+        tclass: u8,
+        payload_len: usize,
+        hop_limit: u8,
+        src: &Ipv6Addr,
+        dst: &Ipv6Addr,
+    ) -> [u8; 40] {
+        let mut hdr = [0; 40];
+        hdr[..4].copy_from_slice(&(6 << 28 | (tclass as u32) << 20).to_be_bytes());
+        hdr[4..6].copy_from_slice(&(payload_len as u16).to_be_bytes());
+        hdr[6] = libc::IPPROTO_ICMPV6 as u8;
+        hdr[7] = hop_limit;
+        hdr[8..24].copy_from_slice(&src.octets());
+        hdr[24..].copy_from_slice(&dst.octets());
+        hdr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipv4_options() {
+        let mut hdr = vec![0x4e, 0xb9, 0, 84, 0x12, 0x34, 0x40, 0, 64, 1, 0, 0];
+        hdr.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        // Record Route with two of three slots used, then a NOP.
+        hdr.extend_from_slice(&[IPOPT_RR, 15, 12, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0]);
+        hdr.push(IPOPT_NOP);
+        // Timestamp with addresses, one entry used and one overflowed hop.
+        hdr.extend_from_slice(&[IPOPT_TS, 20, 13, 0x11, 3, 3, 3, 3, 0, 0, 0, 42]);
+        hdr.extend_from_slice(&[0; 8]);
+
+        let hdr = Ipv4Header::parse(&hdr).unwrap();
+        assert_eq!((hdr.dscp, hdr.ecn), (46, 1));
+        assert_eq!((hdr.total_len, hdr.id), (84, 0x1234));
+        assert!(hdr.dont_fragment() && !hdr.more_fragments());
+        assert_eq!(
+            (hdr.src, hdr.dst),
+            ([10, 0, 0, 1].into(), [10, 0, 0, 2].into())
+        );
+        assert_eq!(
+            hdr.options,
+            vec![
+                Ipv4Option::RecordRoute {
+                    route: vec![[1, 1, 1, 1].into(), [2, 2, 2, 2].into()]
+                },
+                Ipv4Option::Timestamp {
+                    overflow: 1,
+                    flag: 1,
+                    entries: vec![(Some([3, 3, 3, 3].into()), 42)]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ipv6_roundtrip() {
+        let (src, dst) = ("fd00::1".parse().unwrap(), "fd00::2".parse().unwrap());
+        let hdr = Ipv6Header::parse(&Ipv6Header::synthesize(0xb8, 64, 63, &src, &dst)).unwrap();
+        assert_eq!((hdr.dscp, hdr.ecn, hdr.flow_label), (46, 0, 0));
+        assert_eq!((hdr.payload_len, hdr.hop_limit), (64, 63));
+        assert_eq!((hdr.src, hdr.dst), (src, dst));
+    }
+}
@@ -1,214 +1,973 @@
 use std::{
-    convert::TryInto,
-    ffi::CString,
     io,
-    mem::{transmute, zeroed, MaybeUninit},
-    ptr::{self, copy_nonoverlapping},
+    mem::MaybeUninit,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    os::unix::io::{AsRawFd, RawFd},
+    slice,
+    time::{Duration, Instant},
 };
 
-use bytes::{BufMut, BytesMut};
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 
+#[cfg(feature = "tokio")]
+mod asyncio;
+mod checksum;
+mod error;
+mod filter;
+mod ip;
+mod message;
+mod multi;
+mod packet;
+mod payload;
+mod ping;
+mod pmtu;
+mod resolve;
+mod response;
+mod seq;
+mod sys;
+mod timestamp;
+mod trace;
+
+#[cfg(feature = "tokio")]
+pub use asyncio::{AsyncIcmp, AsyncMultiPinger};
+pub use checksum::{checksum, checksum_update, checksum_v6};
+pub use error::{DataError, DecodeError, ResolveError};
+pub use filter::TypeFilter;
+pub use ip::{IpHeader, Ipv4Header, Ipv4Option, Ipv6Header};
+pub use message::{
+    Icmpv4Type, Icmpv6Type, Message, ParameterProblem6Code, ParameterProblemCode, RedirectCode,
+    TimeExceededCode, Unreachable6Code, UnreachableCode,
+};
+pub use multi::{MultiPinger, Target};
+pub use packet::IcmpPacketBuilder;
+pub use payload::Payload;
+pub use ping::{Pinger, Reply, Summary};
+pub use pmtu::PathMtu;
+pub use resolve::{resolve, Resolver, StaticResolver, SystemResolver};
+pub use response::{Response, ResponseRef};
+pub use seq::{SeqStatus, SeqTracker};
+pub use timestamp::{TimestampReply, Timestamps};
+pub use trace::{Hop, Probe, Traceroute};
+
 pub const DEFDATALEN: usize = 56;
 pub const MAXIPLEN: usize = 60;
+#[deprecated(note = "sequences wrap from 65535 to 0 with `u16` arithmetic; no limit applies")]
 pub const MAXSEQ: u16 = u16::MAX;
+pub const TIMESTAMPLEN: usize = 16;
 
-#[derive(Debug)]
-pub struct Response {
-    ttl: u8,
-    typ: u8,
-    cod: u8,
-    sum: u16,
-    idt: u16,
-    seq: u16,
-    dat: Box<[u8]>,
-}
-
-impl Response {
-    pub fn decode(bytes: &[u8], ip_hdr_len: usize) -> Self {
-        let ttl = bytes[8];
-
-        let bytes = &bytes[ip_hdr_len..];
-        let typ = bytes[0];
-        let cod = bytes[1];
-        let sum = u16::from_be_bytes(bytes[2..4].try_into().unwrap());
-        let idt = u16::from_be_bytes(bytes[4..6].try_into().unwrap());
-        let seq = u16::from_be_bytes(bytes[6..8].try_into().unwrap());
-        let dat = Vec::from(&bytes[8..]);
-
-        Self {
-            ttl,
-            typ,
-            cod,
-            sum,
-            idt,
-            seq,
-            dat: dat.into_boxed_slice(),
-        }
-    }
+pub const ICMP_ECHOREPLY: u8 = 0;
+pub const ICMP_DEST_UNREACH: u8 = 3;
+pub const ICMP_ECHO: u8 = 8;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+pub const ICMP_PARAMETERPROB: u8 = 12;
+pub const ICMP_TIMESTAMP: u8 = 13;
+pub const ICMP_TIMESTAMPREPLY: u8 = 14;
+pub const ICMP6_DST_UNREACH: u8 = 1;
+pub const ICMP6_PACKET_TOO_BIG: u8 = 2;
+pub const ICMP6_TIME_EXCEEDED: u8 = 3;
+pub const ICMP6_PARAM_PROB: u8 = 4;
+pub const ICMP6_ECHO_REQUEST: u8 = 128;
+pub const ICMP6_ECHO_REPLY: u8 = 129;
 
-    #[inline]
-    pub fn len(&self) -> usize {
-        8 + self.dat.len()
-    }
+/// ICMP error messages quote at most 576 bytes for IPv4 and 1280 for IPv6, so
+/// receive buffers are never smaller than this.
+const ERRPACKETLEN: usize = 1280;
 
-    #[inline]
-    pub fn ttl(&self) -> u8 {
-        self.ttl
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    V4,
+    V6,
+}
 
+impl Version {
     #[inline]
-    pub fn kind(&self) -> u8 {
-        self.typ
+    pub fn of(addr: &IpAddr) -> Self {
+        match addr {
+            IpAddr::V4(_) => Version::V4,
+            IpAddr::V6(_) => Version::V6,
+        }
     }
 
     #[inline]
-    pub fn code(&self) -> u8 {
-        self.cod
+    pub fn echo_request(self) -> u8 {
+        match self {
+            Version::V4 => ICMP_ECHO,
+            Version::V6 => ICMP6_ECHO_REQUEST,
+        }
     }
 
     #[inline]
-    pub fn checksum(&self) -> u16 {
-        self.sum
+    pub fn echo_reply(self) -> u8 {
+        match self {
+            Version::V4 => ICMP_ECHOREPLY,
+            Version::V6 => ICMP6_ECHO_REPLY,
+        }
     }
 
     #[inline]
-    pub fn ident(&self) -> u16 {
-        self.idt
+    pub fn dest_unreachable(self) -> u8 {
+        match self {
+            Version::V4 => ICMP_DEST_UNREACH,
+            Version::V6 => ICMP6_DST_UNREACH,
+        }
     }
 
     #[inline]
-    pub fn sequence(&self) -> u16 {
-        self.seq
+    pub fn time_exceeded(self) -> u8 {
+        match self {
+            Version::V4 => ICMP_TIME_EXCEEDED,
+            Version::V6 => ICMP6_TIME_EXCEEDED,
+        }
     }
+}
 
-    #[inline]
-    pub fn data(&self) -> &[u8] {
-        &self.dat
-    }
+/// How the ICMP socket is opened. `Datagram` uses Linux "ping sockets", which
+/// need no privileges but let the kernel pick the identifier and strip the IP
+/// header. `Auto` tries `Raw` first and falls back to `Datagram`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketKind {
+    Raw,
+    Datagram,
+    Auto,
 }
 
-#[derive(Debug)]
-pub enum Version {
-    V4,
-    V6,
+impl SocketKind {
+    fn open(self, ver: Version) -> io::Result<(Socket, SocketKind)> {
+        let (domain, proto) = match ver {
+            Version::V4 => (Domain::IPV4, Protocol::ICMPV4),
+            Version::V6 => (Domain::IPV6, Protocol::ICMPV6),
+        };
+        match self {
+            SocketKind::Raw => Ok((Socket::new(domain, Type::RAW, Some(proto))?, self)),
+            SocketKind::Datagram => Ok((Socket::new(domain, Type::DGRAM, Some(proto))?, self)),
+            SocketKind::Auto => match SocketKind::Raw.open(ver) {
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                    SocketKind::Datagram.open(ver)
+                }
+                res => res,
+            },
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Icmp {
-    pub sock: Socket,
+    sock: Socket,
     dst: SockAddr,
     ver: Version,
+    kind: SocketKind,
     typ: u8,
     idt: u16,
     seq: u16,
     dat: Box<[u8]>,
+    payload: Payload,
+    timeout: Option<Duration>,
+    stamp: bool,
 }
 
 impl Icmp {
     pub fn new(ver: Version, dst: &str, idt: u16, len: Option<usize>) -> io::Result<Self> {
-        let sock = match ver {
-            Version::V4 => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))?,
-            Version::V6 => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6))?,
+        Self::with_kind(SocketKind::Raw, ver, dst, idt, len)
+    }
+
+    /// Like `new`, but with a choice of socket kind. For datagram sockets `idt`
+    /// is ignored: the kernel assigns the identifier, see `ident`. Targets the
+    /// first resolved address that has a route. Resolution failures carry a
+    /// `ResolveError`.
+    pub fn with_kind(
+        kind: SocketKind,
+        ver: Version,
+        dst: &str,
+        idt: u16,
+        len: Option<usize>,
+    ) -> io::Result<Self> {
+        resolve::try_each(resolve(ver, dst)?, |dst| {
+            Self::from_addr(kind, dst, idt, len)
+        })
+    }
+
+    /// Resolves `dst` in both families and targets the first address that
+    /// has a route, trying IPv6 and IPv4 alternately, so that callers need
+    /// not pick a `Version` up front.
+    pub fn lookup(kind: SocketKind, dst: &str, idt: u16, len: Option<usize>) -> io::Result<Self> {
+        Self::lookup_with(&SystemResolver, kind, dst, idt, len)
+    }
+
+    /// Like `lookup`, with a custom resolver.
+    pub fn lookup_with<R: Resolver + ?Sized>(
+        resolver: &R,
+        kind: SocketKind,
+        dst: &str,
+        idt: u16,
+        len: Option<usize>,
+    ) -> io::Result<Self> {
+        let addrs = resolve::interleave(resolver.resolve(dst, None)?);
+        resolve::try_each(addrs, |addr| Self::from_addr(kind, addr, idt, len))
+    }
+
+    /// Targets an address directly, skipping name resolution.
+    pub fn from_ip(
+        kind: SocketKind,
+        dst: IpAddr,
+        idt: u16,
+        len: Option<usize>,
+    ) -> io::Result<Self> {
+        Self::from_addr(kind, SocketAddr::new(dst, 0), idt, len)
+    }
+
+    /// Like `from_ip`, but keeps the scope id of IPv6 link-local addresses.
+    pub fn from_addr(
+        kind: SocketKind,
+        dst: SocketAddr,
+        idt: u16,
+        len: Option<usize>,
+    ) -> io::Result<Self> {
+        let mut icmp = Self::open(kind, Version::of(&dst.ip()), idt, len)?;
+        icmp.dst = dst.into();
+        Ok(icmp)
+    }
+
+    /// Opens a socket with no destination yet, for callers that address
+    /// packets themselves through `send_to`.
+    pub(crate) fn open(
+        kind: SocketKind,
+        ver: Version,
+        idt: u16,
+        len: Option<usize>,
+    ) -> io::Result<Self> {
+        let any = match ver {
+            Version::V4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            Version::V6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let (sock, kind) = Self::open_socket(kind, ver, any)?;
+        let idt = match kind {
+            SocketKind::Datagram => local_port(&sock)?.unwrap_or(idt),
+            _ => idt,
         };
         let len = len.unwrap_or(DEFDATALEN);
-        let (_, dst) = unsafe {
-            SockAddr::init(|addr, len| {
-                let mut res = ptr::null_mut();
-                let mut hints: libc::addrinfo = zeroed();
-                match ver {
-                    Version::V4 => hints.ai_family = libc::AF_INET,
-                    Version::V6 => hints.ai_family = libc::AF_INET6,
-                }
-
-                let host = CString::new(dst).unwrap();
-                libc::getaddrinfo(host.as_ptr(), ptr::null(), &hints, &mut res);
-                if res.is_null() {
-                    return Err(std::io::Error::last_os_error());
-                }
-                len.write((*res).ai_addrlen);
-                copy_nonoverlapping((*res).ai_addr, addr.cast(), 1);
-                libc::freeaddrinfo(res);
-                Ok(())
-            })
-        }
-        .unwrap();
 
         Ok(Self {
             sock,
-            dst,
+            dst: SocketAddr::new(any, 0).into(),
             ver,
-            typ: 8,
+            kind,
+            typ: ver.echo_request(),
             idt,
             seq: 0,
             dat: vec![0; len].into_boxed_slice(),
+            payload: Payload::Zeros,
+            timeout: None,
+            stamp: false,
+        })
+    }
+
+    /// Opens a socket receiving the ancillary data we decode. Ping sockets are
+    /// bound to `src` at once, as binding is what assigns their identifier.
+    fn open_socket(
+        kind: SocketKind,
+        ver: Version,
+        src: IpAddr,
+    ) -> io::Result<(Socket, SocketKind)> {
+        let (sock, kind) = kind.open(ver)?;
+        match ver {
+            Version::V4 if kind == SocketKind::Datagram => {
+                sys::setsockopt(&sock, libc::IPPROTO_IP, libc::IP_RECVTTL, 1 as libc::c_int)?
+            }
+            Version::V4 => {}
+            Version::V6 => {
+                for opt in [
+                    libc::IPV6_RECVHOPLIMIT,
+                    libc::IPV6_RECVTCLASS,
+                    libc::IPV6_RECVPKTINFO,
+                ] {
+                    sys::setsockopt(&sock, libc::IPPROTO_IPV6, opt, 1 as libc::c_int)?;
+                }
+            }
+        }
+        if kind == SocketKind::Datagram {
+            sock.bind(&SocketAddr::new(src, 0).into())?;
+        }
+        Ok((sock, kind))
+    }
+
+    /// Sets the TTL or hop limit of outgoing packets, from 1 to 255.
+    pub fn ttl(self, ttl: u32) -> io::Result<Self> {
+        if !(1..=255).contains(&ttl) {
+            return Err(invalid_input("TTL must be between 1 and 255"));
+        }
+        self.set_ttl(ttl)?;
+        Ok(self)
+    }
+
+    /// Sets the IPv4 type of service or the IPv6 traffic class, the DSCP in
+    /// the upper six bits and the ECN field in the lower two.
+    pub fn tos(self, tos: u8) -> io::Result<Self> {
+        match self.ver {
+            Version::V4 => self.sock.set_tos(tos as u32)?,
+            Version::V6 => sys::setsockopt(
+                &self.sock,
+                libc::IPPROTO_IPV6,
+                libc::IPV6_TCLASS,
+                tos as libc::c_int,
+            )?,
+        }
+        Ok(self)
+    }
+
+    /// Sends and receives through the named interface only (SO_BINDTODEVICE).
+    pub fn device(self, name: &str) -> io::Result<Self> {
+        if name.is_empty() || name.len() >= libc::IFNAMSIZ || name.contains('\0') {
+            return Err(invalid_input("invalid interface name"));
+        }
+        self.sock.bind_device(Some(name.as_bytes()))?;
+        Ok(self)
+    }
+
+    /// Sends from `addr`, which must be of our `Version`. Ping sockets are
+    /// bound when opened, so for them this opens a new socket, which gets a
+    /// new identifier and the options set so far.
+    pub fn source(mut self, addr: IpAddr) -> io::Result<Self> {
+        if Version::of(&addr) != self.ver {
+            return Err(invalid_input("source address family does not match"));
+        }
+        match self.kind {
+            SocketKind::Datagram => {
+                let (sock, _) = Self::open_socket(self.kind, self.ver, addr)?;
+                copy_options(&self.sock, &sock, self.ver)?;
+                self.idt = local_port(&sock)?.unwrap_or(self.idt);
+                self.sock = sock;
+            }
+            _ => self.sock.bind(&SocketAddr::new(addr, 0).into())?,
+        }
+        Ok(self)
+    }
+
+    /// Sets the firewall mark of outgoing packets (SO_MARK), for policy
+    /// routing. Needs CAP_NET_ADMIN.
+    pub fn mark(self, mark: u32) -> io::Result<Self> {
+        self.sock.set_mark(mark)?;
+        Ok(self)
+    }
+
+    /// Sets the socket send buffer size. The kernel doubles it and caps it at
+    /// `net.core.wmem_max`.
+    pub fn send_buffer_size(self, size: usize) -> io::Result<Self> {
+        if size == 0 {
+            return Err(invalid_input("buffer size must not be zero"));
+        }
+        self.sock.set_send_buffer_size(size)?;
+        Ok(self)
+    }
+
+    /// Sets the socket receive buffer size. The kernel doubles it and caps it
+    /// at `net.core.rmem_max`.
+    pub fn recv_buffer_size(self, size: usize) -> io::Result<Self> {
+        if size == 0 {
+            return Err(invalid_input("buffer size must not be zero"));
+        }
+        self.sock.set_recv_buffer_size(size)?;
+        Ok(self)
+    }
+
+    /// Has the kernel drop the ICMP types `filter` blocks, rather than `recv`
+    /// skipping them. Only raw sockets see other types than echo replies, so
+    /// ping sockets fail with `io::ErrorKind::Unsupported`.
+    pub fn set_type_filter(&self, filter: &TypeFilter) -> io::Result<()> {
+        if self.kind != SocketKind::Raw {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "type filters need a raw socket",
+            ));
+        }
+        filter.install(&self.sock, self.ver)
+    }
+
+    /// Builder form of `set_type_filter`.
+    pub fn type_filter(self, filter: TypeFilter) -> io::Result<Self> {
+        self.set_type_filter(&filter)?;
+        Ok(self)
+    }
+
+    /// Attaches a BPF program that has the kernel drop echo replies to other
+    /// identifiers than ours, and from other addresses than `src` if given,
+    /// before they are queued. Other messages pass. Ping sockets already only
+    /// get replies to their identifier, so they are not supported.
+    pub fn set_reply_filter(&self, src: Option<IpAddr>) -> io::Result<()> {
+        if self.kind != SocketKind::Raw {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "reply filters need a raw socket",
+            ));
+        }
+        if src.is_some_and(|src| Version::of(&src) != self.ver) {
+            return Err(invalid_input("source address family does not match"));
+        }
+        self.sock
+            .attach_filter(&filter::reply_program(self.ver, self.idt, src))
+    }
+
+    /// Builder form of `set_reply_filter`.
+    pub fn reply_filter(self, src: Option<IpAddr>) -> io::Result<Self> {
+        self.set_reply_filter(src)?;
+        Ok(self)
+    }
+
+    /// Builder form of `set_dont_fragment`.
+    pub fn dont_fragment(self, df: bool) -> io::Result<Self> {
+        self.set_dont_fragment(df)?;
+        Ok(self)
+    }
+
+    /// Sends an echo request, returning the sequence number it carried. The
+    /// sequence then advances, wrapping from 65535 to 0.
+    pub fn send(&mut self) -> io::Result<u16> {
+        let mut buf = vec![0; self.serialize_len()];
+        self.send_with(&mut buf)
+    }
+
+    /// Like `send`, but serializes into `buf` instead of allocating. Fails
+    /// with `io::ErrorKind::InvalidInput` if `buf` is shorter than
+    /// `serialize_len`.
+    pub fn send_with(&mut self, buf: &mut [u8]) -> io::Result<u16> {
+        let buf = buf
+            .get_mut(..self.serialize_len())
+            .ok_or_else(|| invalid_input("buffer too small"))?;
+        self.encode(self.idt, self.seq, buf);
+        self.sock.send_to(buf, &self.dst)?;
+        Ok(self.next_seq())
+    }
+
+    /// Sends an arbitrary message to our destination. Ping sockets only carry
+    /// echo requests, and overwrite their identifier.
+    pub fn send_packet(&self, pkt: &IcmpPacketBuilder) -> io::Result<usize> {
+        if pkt.version() != self.ver {
+            return Err(invalid_input("packet version does not match socket"));
+        }
+        self.sock.send_to(&pkt.build(), &self.dst)
+    }
+
+    /// Sends an ICMPv4 Timestamp request stamped with the current time, using
+    /// and advancing our sequence like `send`. Only raw IPv4 sockets can send
+    /// it; others fail with `io::ErrorKind::Unsupported`.
+    pub fn send_timestamp(&mut self) -> io::Result<u16> {
+        if self.ver != Version::V4 || self.kind != SocketKind::Raw {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "timestamp probes need a raw IPv4 socket",
+            ));
+        }
+        let pkt = IcmpPacketBuilder::timestamp(self.idt, self.seq, timestamp::since_midnight());
+        self.send_packet(&pkt)?;
+        Ok(self.next_seq())
+    }
+
+    /// Waits like `recv` for the Timestamp Reply to the request
+    /// `send_timestamp` sent with sequence `seq`. With `set_timestamp` on,
+    /// the time it `arrived` is the one the kernel stamped it with.
+    pub fn recv_timestamp(&self, seq: u16) -> io::Result<TimestampReply> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut buf = vec![0; (MAXIPLEN + self.serialize_len()).max(ERRPACKETLEN)];
+        let (len, addr, resp, anc) = self.recv_anc(&mut buf, deadline, |resp| {
+            resp.kind() == ICMP_TIMESTAMPREPLY
+                && resp.ident() == self.idt
+                && resp.sequence() == seq
+                && resp.timestamps().is_some()
+        })?;
+        Ok(TimestampReply {
+            len,
+            addr,
+            timestamps: resp.timestamps().unwrap(),
+            resp: resp.into_owned(),
+            arrived: anc
+                .stamp
+                .map_or_else(timestamp::since_midnight, timestamp::at_midnight_offset),
         })
     }
 
-    pub fn send(&mut self) -> io::Result<usize> {
-        let mut buf = BytesMut::with_capacity(self.serialize_len());
-        buf.put_u8(self.typ);
-        buf.put_u8(0);
-        buf.put_u16(0);
-        buf.put_u16(self.idt);
-        buf.put_u16(self.seq);
-        buf.put_slice(&self.dat);
+    /// Sends an echo request to each of `dsts`, with consecutive sequence
+    /// numbers, using as few `sendmmsg` calls as possible. Requests the kernel
+    /// refuses, such as to destinations without a route, are skipped. Returns
+    /// how many were sent, or the first error if none was. The sequence
+    /// advances past all of them.
+    pub fn send_batch(&mut self, dsts: &[SockAddr]) -> io::Result<usize> {
+        let pkts: Vec<_> = dsts
+            .iter()
+            .enumerate()
+            .map(|(i, dst)| (dst, self.idt, self.seq.wrapping_add(i as u16)))
+            .collect();
+        let errs = self.send_batch_to(&pkts, &mut Vec::new());
+        self.seq = self.seq.wrapping_add(dsts.len() as u16);
+        let sent = dsts.len() - errs.len();
+        match errs.into_iter().next() {
+            Some((_, err)) if sent == 0 => Err(err),
+            _ => Ok(sent),
+        }
+    }
+
+    /// Sends an echo request per `(destination, identifier, sequence)`,
+    /// serializing them into `buf` and leaving our own sequence untouched.
+    /// Returns the index and error of each request the kernel refused.
+    pub(crate) fn send_batch_to(
+        &self,
+        pkts: &[(&SockAddr, u16, u16)],
+        buf: &mut Vec<u8>,
+    ) -> Vec<(usize, io::Error)> {
+        let len = self.serialize_len();
+        buf.resize(len * pkts.len(), 0);
+        for (chunk, &(_, idt, seq)) in buf.chunks_exact_mut(len).zip(pkts) {
+            self.encode(idt, seq, chunk);
+        }
+        let msgs: Vec<_> = buf
+            .chunks_exact(len)
+            .zip(pkts)
+            .map(|(chunk, &(dst, _, _))| (chunk, dst))
+            .collect();
+        sys::send_mmsg(&self.sock, &msgs)
+    }
 
-        let sum = checksum(&buf).to_be_bytes();
-        let mut buf = buf.to_vec();
-        buf[2] = sum[0];
-        buf[3] = sum[1];
+    /// Serializes an echo request into `buf`, which is exactly `serialize_len`
+    /// bytes long.
+    fn encode(&self, idt: u16, seq: u16, buf: &mut [u8]) {
+        buf[0] = self.typ;
+        buf[1] = 0;
+        buf[2..4].fill(0);
+        buf[4..6].copy_from_slice(&idt.to_be_bytes());
+        buf[6..8].copy_from_slice(&seq.to_be_bytes());
+        buf[8..].copy_from_slice(&self.dat);
+        if self.stamp && self.dat.len() >= TIMESTAMPLEN {
+            let now = sys::monotonic();
+            buf[8..16].copy_from_slice(&now.as_secs().to_be_bytes());
+            buf[16..24].copy_from_slice(&(now.subsec_nanos() as u64).to_be_bytes());
+        }
 
-        let len = self.sock.send_to(&buf, &self.dst)?;
-        self.seq = (self.seq + 1) % MAXSEQ;
-        Ok(len)
+        // The kernel fills in the ICMPv6 checksum, which covers a pseudo-header,
+        // and the checksum of ping sockets, whose identifier it rewrites.
+        if self.ver == Version::V4 && self.kind == SocketKind::Raw {
+            let sum = checksum(buf);
+            buf[2..4].copy_from_slice(&sum.to_be_bytes());
+        }
     }
 
+    /// Waits for an echo reply carrying our identifier, for at most the default
+    /// timeout if one is set. Raw sockets see every ICMP packet on the host, so
+    /// malformed packets are skipped like foreign ones rather than reported.
     pub fn recv(&self) -> io::Result<(usize, SockAddr, Response)> {
-        let mut buf = vec![MaybeUninit::uninit(); MAXIPLEN + self.serialize_len()];
+        self.recv_deadline(self.timeout.map(|timeout| Instant::now() + timeout))
+    }
+
+    /// Like `recv`, but receives into `buf` and borrows the reply from it
+    /// instead of allocating. `buf` should hold `MAXIPLEN + serialize_len`
+    /// bytes, as longer packets are truncated and then fail to decode.
+    pub fn recv_into<'a>(
+        &self,
+        buf: &'a mut [u8],
+    ) -> io::Result<(usize, SockAddr, ResponseRef<'a>)> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        self.recv_ref(buf, deadline, |resp| self.is_reply(resp))
+    }
+
+    /// Fails with `io::ErrorKind::TimedOut` if no reply arrives in time.
+    pub fn recv_timeout(&self, timeout: Duration) -> io::Result<(usize, SockAddr, Response)> {
+        self.recv_deadline(Some(Instant::now() + timeout))
+    }
+
+    /// Fails with `io::ErrorKind::TimedOut` if no reply arrives by `deadline`.
+    /// Foreign packets received meanwhile do not extend the deadline.
+    pub fn recv_until(&self, deadline: Instant) -> io::Result<(usize, SockAddr, Response)> {
+        self.recv_deadline(Some(deadline))
+    }
+
+    pub(crate) fn recv_deadline(
+        &self,
+        deadline: Option<Instant>,
+    ) -> io::Result<(usize, SockAddr, Response)> {
+        self.recv_filter(deadline, |resp| self.is_reply(resp))
+    }
+
+    fn is_reply(&self, resp: &ResponseRef<'_>) -> bool {
+        resp.kind() == self.ver.echo_reply() && resp.ident() == self.idt
+    }
 
+    /// Receives the next well-formed message accepted by `filter`.
+    pub(crate) fn recv_filter<F>(
+        &self,
+        deadline: Option<Instant>,
+        filter: F,
+    ) -> io::Result<(usize, SockAddr, Response)>
+    where
+        F: FnMut(&ResponseRef<'_>) -> bool,
+    {
+        let mut buf = vec![0; (MAXIPLEN + self.serialize_len()).max(ERRPACKETLEN)];
+        let (len, addr, resp) = self.recv_ref(&mut buf, deadline, filter)?;
+        Ok((len, addr, resp.into_owned()))
+    }
+
+    /// Like `recv_filter`, borrowing the message from `buf`.
+    pub(crate) fn recv_ref<'a, F>(
+        &self,
+        buf: &'a mut [u8],
+        deadline: Option<Instant>,
+        filter: F,
+    ) -> io::Result<(usize, SockAddr, ResponseRef<'a>)>
+    where
+        F: FnMut(&ResponseRef<'_>) -> bool,
+    {
+        let (len, addr, mut resp, anc) = self.recv_anc(buf, deadline, filter)?;
+        if self.stamp && resp.kind() == self.ver.echo_reply() {
+            resp.stamp_rtt(received_at(anc.stamp));
+        }
+        Ok((len, addr, resp))
+    }
+
+    /// Like `recv_ref`, also returning the ancillary data of the message,
+    /// without computing the RTT from it.
+    fn recv_anc<'a, F>(
+        &self,
+        buf: &'a mut [u8],
+        deadline: Option<Instant>,
+        mut filter: F,
+    ) -> io::Result<(usize, SockAddr, ResponseRef<'a>, sys::Ancillary)>
+    where
+        F: FnMut(&ResponseRef<'_>) -> bool,
+    {
+        // Returning a message borrowed from `buf` out of the loop that keeps
+        // writing to it is beyond the borrow checker, so the buffer is
+        // accessed through a raw pointer. Each message is received into it
+        // while no message borrowed from it is live, and the one returned is
+        // the last written.
+        let (ptr, cap) = (buf.as_mut_ptr(), buf.len());
         loop {
-            let (len, addr) = self.sock.recv_from(&mut buf)?;
-            let dat: &[u8] = unsafe { transmute(&buf[..len]) };
-            let ip_hdr_len = 4 * (dat[0] & 0xf) as usize;
-            let idt = u16::from_be_bytes(dat[ip_hdr_len + 4..ip_hdr_len + 6].try_into().unwrap());
-            if idt != self.idt {
-                continue;
+            if let Some(deadline) = deadline {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                if timeout == Duration::ZERO || !sys::poll_read(&self.sock, timeout)? {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "recv timed out"));
+                }
+            }
+            // recvmsg only ever writes initialized bytes.
+            let uninit = unsafe { slice::from_raw_parts_mut(ptr.cast::<MaybeUninit<u8>>(), cap) };
+            let (len, addr, anc) = sys::recv_msg(&self.sock, uninit)?;
+            let dat: &'a [u8] = unsafe { slice::from_raw_parts(ptr, len.min(cap)) };
+            match self.decode(dat, &addr, &anc) {
+                Ok(resp) if filter(&resp) => return Ok((len, addr, resp, anc)),
+                _ => continue,
             }
-            let resp = Response::decode(&dat, ip_hdr_len);
+        }
+    }
+
+    /// Receives up to `max` echo replies carrying our identifier using as
+    /// few `recvmmsg` calls as possible. Waits like `recv` for the first
+    /// reply, then returns the others that have already arrived.
+    pub fn recv_batch(&self, max: usize) -> io::Result<Vec<(usize, SockAddr, Response)>> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        self.recv_batch_filter(deadline, max, &mut Vec::new(), |resp| self.is_reply(resp))
+    }
+
+    /// Like `recv_filter`, for up to `max` messages at once, received into
+    /// `buf`, which grows as needed.
+    pub(crate) fn recv_batch_filter<F>(
+        &self,
+        deadline: Option<Instant>,
+        max: usize,
+        buf: &mut Vec<u8>,
+        mut filter: F,
+    ) -> io::Result<Vec<(usize, SockAddr, Response)>>
+    where
+        F: FnMut(&ResponseRef<'_>) -> bool,
+    {
+        let len = (MAXIPLEN + self.serialize_len()).max(ERRPACKETLEN);
+        if buf.len() < len * max.max(1) {
+            buf.resize(len * max.max(1), 0);
+        }
+        let mut replies = Vec::new();
+
+        while replies.is_empty() {
+            if let Some(deadline) = deadline {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                if timeout == Duration::ZERO || !sys::poll_read(&self.sock, timeout)? {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "recv timed out"));
+                }
+            }
+            let mut bufs: Vec<&mut [u8]> = buf.chunks_exact_mut(len).take(max.max(1)).collect();
+            let msgs = sys::recv_mmsg(&self.sock, &mut bufs)?;
+            for (buf, (len, addr, anc)) in bufs.iter().zip(msgs) {
+                let mut resp = match self.decode(&buf[..len], &addr, &anc) {
+                    Ok(resp) if filter(&resp) => resp,
+                    _ => continue,
+                };
+                if self.stamp && resp.kind() == self.ver.echo_reply() {
+                    resp.stamp_rtt(received_at(anc.stamp));
+                }
+                replies.push((len, addr, resp.into_owned()));
+            }
+        }
+        Ok(replies)
+    }
+
+    fn decode<'a>(
+        &self,
+        dat: &'a [u8],
+        src: &SockAddr,
+        anc: &sys::Ancillary,
+    ) -> Result<ResponseRef<'a>, DecodeError> {
+        let mut resp = match (self.ver, self.kind) {
+            (Version::V4, SocketKind::Raw) => ResponseRef::decode(dat)?,
+            _ => ResponseRef::decode_icmp(self.ver, dat, anc.ttl.unwrap_or(0))?,
+        };
+        if let Some(SocketAddr::V6(src)) = src.as_socket() {
+            let dst = anc.dst.unwrap_or(Ipv6Addr::UNSPECIFIED);
+            resp.set_ipv6_header(src.ip(), &dst, anc.tclass.unwrap_or(0));
+        }
+        Ok(resp)
+    }
+
+    #[inline]
+    pub fn timestamp(&self) -> bool {
+        self.stamp
+    }
+
+    /// Makes `send` write a monotonic timestamp into the first `TIMESTAMPLEN`
+    /// bytes of the data, from which replies compute their `rtt`. Data shorter
+    /// than that is left alone and no RTT is reported.
+    pub fn set_timestamp(&mut self, stamp: bool) -> io::Result<()> {
+        sys::setsockopt(
+            &self.sock,
+            libc::SOL_SOCKET,
+            libc::SO_TIMESTAMPNS,
+            stamp as libc::c_int,
+        )?;
+        self.stamp = stamp;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets the default timeout used by `recv`; `None` waits forever.
+    #[inline]
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
 
-            return Ok((len, addr, resp));
+    /// Sets the IPv4 TTL or IPv6 unicast hop limit of outgoing packets.
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        match self.ver {
+            Version::V4 => self.sock.set_ttl(ttl),
+            Version::V6 => self.sock.set_unicast_hops_v6(ttl),
         }
     }
 
+    /// The IPv4 TTL or IPv6 unicast hop limit of outgoing packets.
+    pub(crate) fn current_ttl(&self) -> io::Result<u32> {
+        match self.ver {
+            Version::V4 => self.sock.ttl(),
+            Version::V6 => self.sock.unicast_hops_v6(),
+        }
+    }
+
+    /// Sets Don't Fragment on outgoing IPv4 packets and stops the kernel from
+    /// fragmenting IPv6 ones. Packets are not checked against the cached path
+    /// MTU either, so that they reach the link too small for them.
+    pub fn set_dont_fragment(&self, df: bool) -> io::Result<()> {
+        let (level, name, val) = match (self.ver, df) {
+            (Version::V4, true) => (
+                libc::IPPROTO_IP,
+                libc::IP_MTU_DISCOVER,
+                libc::IP_PMTUDISC_PROBE,
+            ),
+            (Version::V4, false) => (
+                libc::IPPROTO_IP,
+                libc::IP_MTU_DISCOVER,
+                libc::IP_PMTUDISC_DONT,
+            ),
+            (Version::V6, true) => (
+                libc::IPPROTO_IPV6,
+                libc::IPV6_MTU_DISCOVER,
+                libc::IPV6_PMTUDISC_PROBE,
+            ),
+            (Version::V6, false) => (
+                libc::IPPROTO_IPV6,
+                libc::IPV6_MTU_DISCOVER,
+                libc::IPV6_PMTUDISC_DONT,
+            ),
+        };
+        sys::setsockopt(&self.sock, level, name, val)
+    }
+
+    /// The underlying socket, for options this crate does not cover. The
+    /// options set on it survive `source`.
+    #[inline]
+    pub fn socket(&self) -> &Socket {
+        &self.sock
+    }
+
+    #[inline]
+    pub fn version(&self) -> Version {
+        self.ver
+    }
+
+    /// Address `send` targets.
+    #[inline]
+    pub fn destination(&self) -> &SockAddr {
+        &self.dst
+    }
+
+    #[inline]
+    pub fn socket_kind(&self) -> SocketKind {
+        self.kind
+    }
+
+    #[inline]
+    pub fn ident(&self) -> u16 {
+        self.idt
+    }
+
+    /// Sequence number the next `send` will use.
+    #[inline]
+    pub fn sequence(&self) -> u16 {
+        self.seq
+    }
+
+    /// Sets the sequence number the next `send` will use, 0 when opened.
+    #[inline]
+    pub fn set_sequence(&mut self, seq: u16) {
+        self.seq = seq;
+    }
+
+    /// Returns the current sequence and advances it.
+    fn next_seq(&mut self) -> u16 {
+        let seq = self.seq;
+        self.seq = seq.wrapping_add(1);
+        seq
+    }
+
     #[inline]
     pub fn data_mut(&mut self) -> &mut [u8] {
         &mut self.dat
     }
 
+    /// Resizes the data sent with each request, keeping its start and filling
+    /// any new bytes from the payload.
+    pub fn set_data_len(&mut self, len: usize) {
+        let mut dat = std::mem::take(&mut self.dat).into_vec();
+        let old = dat.len().min(len);
+        dat.resize(len, 0);
+        self.payload.fill(&mut dat, old);
+        self.dat = dat.into_boxed_slice();
+    }
+
+    #[inline]
+    pub fn payload(&self) -> &Payload {
+        &self.payload
+    }
+
+    /// Fills the data sent with each request from `payload`. `Payload::Bytes`
+    /// also sets its length.
+    pub fn set_payload(&mut self, payload: Payload) {
+        if let Payload::Bytes(bytes) = &payload {
+            self.dat = vec![0; bytes.len()].into_boxed_slice();
+        }
+        payload.fill(&mut self.dat, 0);
+        self.payload = payload;
+    }
+
+    /// Checks that a reply echoed our data intact, as far as it is ours: the
+    /// timestamp written by `send` is left out.
+    pub fn verify_data(&self, resp: &ResponseRef<'_>) -> Result<(), DataError> {
+        let dat = resp.data();
+        if dat.len() < self.dat.len() {
+            return Err(DataError::Truncated {
+                len: dat.len(),
+                expected: self.dat.len(),
+            });
+        }
+        let from = match self.stamp && self.dat.len() >= TIMESTAMPLEN {
+            true => TIMESTAMPLEN,
+            false => 0,
+        };
+        match (from..self.dat.len()).find(|&i| dat[i] != self.dat[i]) {
+            Some(offset) => Err(DataError::Mismatch {
+                offset,
+                expected: self.dat[offset],
+                found: dat[offset],
+            }),
+            None => Ok(()),
+        }
+    }
+
     #[inline]
     pub fn serialize_len(&self) -> usize {
         8 + self.dat.len()
     }
 }
 
-pub fn checksum(bytes: &[u8]) -> u16 {
-    let mut sum = 0u32;
-    let skip = 1;
-    bytes.chunks_exact(2).enumerate().for_each(|(i, buf)| {
-        if i != skip {
-            sum += u16::from_be_bytes(buf.try_into().unwrap()) as u32;
+impl AsRawFd for Icmp {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.as_raw_fd()
+    }
+}
+
+/// The port a ping socket is bound to, which is its identifier.
+fn local_port(sock: &Socket) -> io::Result<Option<u16>> {
+    Ok(sock.local_addr()?.as_socket().map(|addr| addr.port()))
+}
+
+/// Carries the options of `old` that differ from those of the freshly opened
+/// `new` over to it. Filters are left out, as only raw sockets take them.
+fn copy_options(old: &Socket, new: &Socket, ver: Version) -> io::Result<()> {
+    let (ip, ttl, tos, pmtu) = match ver {
+        Version::V4 => (
+            libc::IPPROTO_IP,
+            libc::IP_TTL,
+            libc::IP_TOS,
+            libc::IP_MTU_DISCOVER,
+        ),
+        Version::V6 => (
+            libc::IPPROTO_IPV6,
+            libc::IPV6_UNICAST_HOPS,
+            libc::IPV6_TCLASS,
+            libc::IPV6_MTU_DISCOVER,
+        ),
+    };
+    for (level, name) in [
+        (ip, ttl),
+        (ip, tos),
+        (ip, pmtu),
+        (libc::SOL_SOCKET, libc::SO_MARK),
+        (libc::SOL_SOCKET, libc::SO_TIMESTAMPNS),
+    ] {
+        let val: libc::c_int = sys::getsockopt(old, level, name)?;
+        if val != sys::getsockopt::<libc::c_int>(new, level, name)? {
+            sys::setsockopt(new, level, name, val)?;
         }
-    });
+    }
 
-    while sum >> 16 != 0 {
-        sum = (sum >> 16) + (sum & 0xffff);
+    // The kernel reports the doubled sizes it keeps.
+    let size = old.send_buffer_size()?;
+    if size != new.send_buffer_size()? {
+        new.set_send_buffer_size(size / 2)?;
+    }
+    let size = old.recv_buffer_size()?;
+    if size != new.recv_buffer_size()? {
+        new.set_recv_buffer_size(size / 2)?;
     }
+    if let Some(dev) = old.device()? {
+        new.bind_device(Some(&dev))?;
+    }
+    Ok(())
+}
 
-    !sum as u16
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
 }
 
-#[cfg(test)]
-mod tests {
-    // TODO: Implement test code
+/// Maps the kernel receive timestamp, taken on the realtime clock, onto the
+/// monotonic clock used for send timestamps.
+fn received_at(stamp: Option<Duration>) -> Duration {
+    let now = sys::monotonic();
+    match stamp {
+        Some(stamp) => now - sys::realtime().saturating_sub(stamp).min(now),
+        None => now,
+    }
 }
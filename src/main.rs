@@ -0,0 +1,341 @@
+//! `icmpp`, a ping work-alike taking the common options of iputils ping.
+
+use std::{
+    env,
+    io::{self, Write},
+    net::IpAddr,
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    time::Duration,
+};
+
+use icmpp::{DataError, Icmp, Payload, Pinger, Reply, SocketKind, Version, TIMESTAMPLEN};
+
+const USAGE: &str = "\
+Usage: icmpp [options] <destination>
+
+Options:
+  -c <count>     stop after <count> replies
+  -f             flood ping
+  -i <interval>  seconds between sending each packet
+  -I <iface>     interface name or source address
+  -p <pattern>   hex pattern to fill the data with
+  -q             quiet output
+  -s <size>      number of data bytes to send
+  -t <ttl>       time to live
+  -W <timeout>   seconds to wait for a response
+  -w <deadline>  seconds before exiting, whatever happens
+  -4             use IPv4
+  -6             use IPv6
+  -h             print this help";
+
+/// Flood pings are paced at 100 per second while replies are outstanding.
+const FLOOD_INTERVAL: Duration = Duration::from_millis(10);
+
+static STOP: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// A socket option given on the command line.
+#[derive(Debug, PartialEq, Eq)]
+enum Setting<'a> {
+    Source(IpAddr),
+    Device(&'a str),
+    Ttl(u32),
+}
+
+#[derive(Debug, Default)]
+struct Opts {
+    count: Option<u64>,
+    interval: Option<Duration>,
+    size: Option<usize>,
+    ttl: Option<u32>,
+    timeout: Option<Duration>,
+    deadline: Option<Duration>,
+    quiet: bool,
+    flood: bool,
+    ver: Option<Version>,
+    iface: Option<String>,
+    payload: Option<Payload>,
+    host: String,
+}
+
+impl Opts {
+    /// Parses arguments the way getopt does, so that `-c3` and `-qc 3` work.
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut opts = Opts::default();
+        let mut host = None;
+
+        while let Some(arg) = args.next() {
+            let flags = match arg.strip_prefix('-') {
+                Some(flags) if !flags.is_empty() => flags,
+                _ => {
+                    if host.replace(arg).is_some() {
+                        return Err("only one destination is supported".to_string());
+                    }
+                    continue;
+                }
+            };
+            for (i, flag) in flags.char_indices() {
+                match flag {
+                    'q' => opts.quiet = true,
+                    'f' => opts.flood = true,
+                    '4' => opts.ver = Some(Version::V4),
+                    '6' => opts.ver = Some(Version::V6),
+                    'h' => return Err(String::new()),
+                    'c' | 'i' | 'I' | 'p' | 's' | 't' | 'W' | 'w' => {
+                        let rest = &flags[i + flag.len_utf8()..];
+                        let val = match rest.is_empty() {
+                            true => args.next().ok_or_else(|| {
+                                format!("option requires an argument -- '{}'", flag)
+                            })?,
+                            false => rest.to_string(),
+                        };
+                        opts.set(flag, &val)
+                            .ok_or_else(|| format!("invalid argument: '{}'", val))?;
+                        break;
+                    }
+                    _ => return Err(format!("invalid option -- '{}'", flag)),
+                }
+            }
+        }
+
+        opts.host = host.ok_or_else(|| "usage error: destination address required".to_string())?;
+        Ok(opts)
+    }
+
+    fn set(&mut self, flag: char, val: &str) -> Option<()> {
+        match flag {
+            'c' => self.count = Some(val.parse().ok().filter(|&count| count > 0)?),
+            'i' => self.interval = Some(secs(val)?),
+            'I' => self.iface = Some(val.to_string()),
+            'p' => self.payload = Some(Payload::from_hex(val)?),
+            's' => self.size = Some(val.parse().ok().filter(|&size| size <= 65507)?),
+            't' => self.ttl = Some(val.parse().ok()?),
+            'W' => self.timeout = Some(secs(val)?),
+            'w' => self.deadline = Some(secs(val)?),
+            _ => unreachable!(),
+        }
+        Some(())
+    }
+
+    /// The socket options to apply, in order. The source comes first, as
+    /// binding a ping socket to it opens a new one.
+    fn settings(&self) -> Vec<Setting<'_>> {
+        let mut settings = Vec::new();
+        if let Some(iface) = &self.iface {
+            settings.push(match iface.parse() {
+                Ok(src) => Setting::Source(src),
+                Err(_) => Setting::Device(iface),
+            });
+        }
+        if let Some(ttl) = self.ttl {
+            settings.push(Setting::Ttl(ttl));
+        }
+        settings
+    }
+}
+
+/// Parses a non-negative number of seconds, which may have a fraction.
+fn secs(val: &str) -> Option<Duration> {
+    let secs: f64 = val.parse().ok()?;
+    match secs.is_finite() && secs >= 0.0 {
+        true => Some(Duration::from_secs_f64(secs)),
+        false => None,
+    }
+}
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    if let Some(stop) = STOP.get() {
+        stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn main() {
+    let opts = match Opts::parse(env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(msg) if msg.is_empty() => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(msg) => {
+            eprintln!("icmpp: {}\n\n{}", msg, USAGE);
+            process::exit(2);
+        }
+    };
+    match ping(&opts) {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("icmpp: {}", e);
+            process::exit(2);
+        }
+    }
+}
+
+fn open(opts: &Opts) -> io::Result<Icmp> {
+    let idt = process::id() as u16;
+    let mut icmp = match opts.ver {
+        Some(ver) => Icmp::with_kind(SocketKind::Auto, ver, &opts.host, idt, opts.size),
+        None => Icmp::lookup(SocketKind::Auto, &opts.host, idt, opts.size),
+    }
+    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", opts.host, e)))?;
+
+    for setting in opts.settings() {
+        icmp = match setting {
+            Setting::Source(src) => icmp.source(src)?,
+            Setting::Device(name) => icmp.device(name)?,
+            Setting::Ttl(ttl) => icmp.ttl(ttl)?,
+        };
+    }
+    if let Some(payload) = &opts.payload {
+        icmp.set_payload(payload.clone());
+    }
+    let len = icmp.data_mut().len();
+    icmp.set_timestamp(len >= TIMESTAMPLEN)?;
+    // Number requests from 1, as ping does.
+    icmp.set_sequence(1);
+    Ok(icmp)
+}
+
+fn ping(opts: &Opts) -> io::Result<i32> {
+    let mut icmp = open(opts)?;
+    let len = icmp.data_mut().len();
+    let addr = icmp.destination().as_socket().map(|addr| addr.ip());
+    let addr = addr.map_or_else(|| opts.host.clone(), |addr| addr.to_string());
+
+    if let Some(Payload::Pattern(pat)) = &opts.payload {
+        let hex: String = pat.iter().map(|b| format!("{:02x}", b)).collect();
+        println!("PATTERN: 0x{}", hex);
+    }
+    match icmp.version() {
+        Version::V4 => println!(
+            "PING {} ({}) {}({}) bytes of data.",
+            opts.host,
+            addr,
+            len,
+            len + 28
+        ),
+        Version::V6 => println!("PING {} ({}) {} data bytes", opts.host, addr, len),
+    }
+
+    let stop = STOP.get_or_init(|| Arc::new(AtomicBool::new(false)));
+    let handler: extern "C" fn(libc::c_int) = on_interrupt;
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+
+    let interval = match (opts.interval, opts.flood) {
+        (Some(interval), _) => interval,
+        (None, true) => FLOOD_INTERVAL,
+        (None, false) => Duration::from_secs(1),
+    };
+    let mut pinger = Pinger::new(icmp)
+        .count(opts.count)
+        .interval(interval)
+        .deadline(opts.deadline)
+        .flood(opts.flood)
+        .stop_on(stop.clone());
+    if let Some(timeout) = opts.timeout {
+        pinger = pinger.timeout(timeout);
+    }
+
+    let summary = if opts.flood && !opts.quiet {
+        // A dot per request, rubbed out by its reply, leaves one per loss.
+        pinger.run_with(|_| flood_mark("."), |_| flood_mark("\x08 \x08"))?
+    } else {
+        pinger.run(|reply| {
+            if !opts.quiet {
+                print_reply(reply);
+            }
+        })?
+    };
+
+    println!("\n--- {} ping statistics ---", opts.host);
+    println!("{}", summary);
+
+    let short = opts.deadline.is_some() && opts.count.is_some_and(|count| summary.received < count);
+    Ok(if summary.received == 0 || short { 1 } else { 0 })
+}
+
+fn flood_mark(mark: &str) {
+    let mut out = io::stdout();
+    let _ = out.write_all(mark.as_bytes()).and_then(|_| out.flush());
+}
+
+fn print_reply(reply: &Reply) {
+    let from = match reply.addr.as_socket() {
+        Some(addr) => addr.ip().to_string(),
+        None => "?".to_string(),
+    };
+    let mut line = format!(
+        "{} bytes from {}: icmp_seq={} ttl={} time={} ms",
+        reply.resp.len(),
+        from,
+        reply.resp.sequence(),
+        reply.resp.ttl(),
+        fmt_rtt(reply.rtt)
+    );
+    if reply.dup {
+        line.push_str(" (DUP!)");
+    }
+    match reply.corrupt {
+        Some(DataError::Truncated { .. }) => line.push_str(" (truncated)"),
+        Some(err) => line = format!("{}\n{}", line, err),
+        None => {}
+    }
+    println!("{}", line);
+}
+
+/// Formats an RTT in milliseconds with three significant digits or more, as
+/// ping does.
+fn fmt_rtt(rtt: Duration) -> String {
+    let ms = rtt.as_secs_f64() * 1000.0;
+    match ms {
+        ms if ms >= 100.0 => format!("{:.0}", ms),
+        ms if ms >= 10.0 => format!("{:.1}", ms),
+        ms if ms >= 1.0 => format!("{:.2}", ms),
+        ms => format!("{:.3}", ms),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Opts, String> {
+        Opts::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parse_getopt_style() {
+        let opts = parse("-qc3 -i 0.2 -6 -p ab -w5 ::1").unwrap();
+        assert!(opts.quiet && !opts.flood);
+        assert_eq!(opts.count, Some(3));
+        assert_eq!(opts.interval, Some(Duration::from_millis(200)));
+        assert_eq!(opts.deadline, Some(Duration::from_secs(5)));
+        assert_eq!(opts.ver, Some(Version::V6));
+        assert_eq!(opts.payload, Some(Payload::Pattern(vec![0xab])));
+        assert_eq!(opts.host, "::1");
+
+        assert!(parse("-c 0 ::1").is_err());
+        assert!(parse("-i -1 ::1").is_err());
+        assert!(parse("-x ::1").is_err());
+        assert!(parse("-c").is_err());
+        assert!(parse("-q").is_err());
+    }
+
+    #[test]
+    fn source_before_ttl() {
+        let opts = parse("-t 5 -I 192.0.2.1 host").unwrap();
+        assert_eq!(
+            opts.settings(),
+            [
+                Setting::Source("192.0.2.1".parse().unwrap()),
+                Setting::Ttl(5)
+            ]
+        );
+        let opts = parse("-t5 -I eth0 host").unwrap();
+        assert_eq!(opts.settings(), [Setting::Device("eth0"), Setting::Ttl(5)]);
+    }
+}
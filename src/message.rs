@@ -0,0 +1,332 @@
+use crate::Version;
+
+macro_rules! codes {
+    ($(#[$meta:meta])* pub enum $name:ident { $($var:ident = $val:expr,)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($var,)*
+            Other(u8),
+        }
+
+        impl From<u8> for $name {
+            fn from(code: u8) -> Self {
+                match code {
+                    $($val => $name::$var,)*
+                    code => $name::Other(code),
+                }
+            }
+        }
+
+        impl From<$name> for u8 {
+            fn from(code: $name) -> Self {
+                match code {
+                    $($name::$var => $val,)*
+                    $name::Other(code) => code,
+                }
+            }
+        }
+    };
+}
+
+codes! {
+    /// Codes of ICMPv4 Destination Unreachable (RFC 792, RFC 1812).
+    pub enum UnreachableCode {
+        Network = 0,
+        Host = 1,
+        Protocol = 2,
+        Port = 3,
+        FragmentationNeeded = 4,
+        SourceRouteFailed = 5,
+        NetworkUnknown = 6,
+        HostUnknown = 7,
+        SourceHostIsolated = 8,
+        NetworkProhibited = 9,
+        HostProhibited = 10,
+        NetworkTos = 11,
+        HostTos = 12,
+        CommunicationProhibited = 13,
+        HostPrecedenceViolation = 14,
+        PrecedenceCutoff = 15,
+    }
+}
+
+codes! {
+    /// Codes of ICMPv4 Redirect.
+    pub enum RedirectCode {
+        Network = 0,
+        Host = 1,
+        TosNetwork = 2,
+        TosHost = 3,
+    }
+}
+
+codes! {
+    /// Codes of Time Exceeded, shared by ICMPv4 and ICMPv6.
+    pub enum TimeExceededCode {
+        TtlExceeded = 0,
+        FragmentReassembly = 1,
+    }
+}
+
+codes! {
+    /// Codes of ICMPv4 Parameter Problem.
+    pub enum ParameterProblemCode {
+        Pointer = 0,
+        MissingOption = 1,
+        BadLength = 2,
+    }
+}
+
+codes! {
+    /// Codes of ICMPv6 Destination Unreachable (RFC 4443).
+    pub enum Unreachable6Code {
+        NoRoute = 0,
+        AdminProhibited = 1,
+        BeyondScope = 2,
+        Address = 3,
+        Port = 4,
+        SourcePolicyFailed = 5,
+        RejectRoute = 6,
+    }
+}
+
+codes! {
+    /// Codes of ICMPv6 Parameter Problem.
+    pub enum ParameterProblem6Code {
+        ErroneousHeader = 0,
+        UnrecognizedNextHeader = 1,
+        UnrecognizedOption = 2,
+    }
+}
+
+/// ICMPv4 message types. Types that define no codes are only recognised with
+/// code 0; anything else becomes `Unknown`, so that conversion back to the
+/// wire is lossless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Icmpv4Type {
+    EchoReply,
+    DestinationUnreachable { code: UnreachableCode },
+    SourceQuench,
+    Redirect { code: RedirectCode },
+    Echo,
+    RouterAdvertisement,
+    RouterSolicitation,
+    TimeExceeded { code: TimeExceededCode },
+    ParameterProblem { code: ParameterProblemCode },
+    Timestamp,
+    TimestampReply,
+    InformationRequest,
+    InformationReply,
+    AddressMaskRequest,
+    AddressMaskReply,
+    Unknown { typ: u8, code: u8 },
+}
+
+impl Icmpv4Type {
+    pub fn from_wire(typ: u8, code: u8) -> Self {
+        match (typ, code) {
+            (0, 0) => Icmpv4Type::EchoReply,
+            (3, code) => Icmpv4Type::DestinationUnreachable { code: code.into() },
+            (4, 0) => Icmpv4Type::SourceQuench,
+            (5, code) => Icmpv4Type::Redirect { code: code.into() },
+            (8, 0) => Icmpv4Type::Echo,
+            (9, 0) => Icmpv4Type::RouterAdvertisement,
+            (10, 0) => Icmpv4Type::RouterSolicitation,
+            (11, code) => Icmpv4Type::TimeExceeded { code: code.into() },
+            (12, code) => Icmpv4Type::ParameterProblem { code: code.into() },
+            (13, 0) => Icmpv4Type::Timestamp,
+            (14, 0) => Icmpv4Type::TimestampReply,
+            (15, 0) => Icmpv4Type::InformationRequest,
+            (16, 0) => Icmpv4Type::InformationReply,
+            (17, 0) => Icmpv4Type::AddressMaskRequest,
+            (18, 0) => Icmpv4Type::AddressMaskReply,
+            (typ, code) => Icmpv4Type::Unknown { typ, code },
+        }
+    }
+
+    pub fn to_wire(self) -> (u8, u8) {
+        match self {
+            Icmpv4Type::EchoReply => (0, 0),
+            Icmpv4Type::DestinationUnreachable { code } => (3, code.into()),
+            Icmpv4Type::SourceQuench => (4, 0),
+            Icmpv4Type::Redirect { code } => (5, code.into()),
+            Icmpv4Type::Echo => (8, 0),
+            Icmpv4Type::RouterAdvertisement => (9, 0),
+            Icmpv4Type::RouterSolicitation => (10, 0),
+            Icmpv4Type::TimeExceeded { code } => (11, code.into()),
+            Icmpv4Type::ParameterProblem { code } => (12, code.into()),
+            Icmpv4Type::Timestamp => (13, 0),
+            Icmpv4Type::TimestampReply => (14, 0),
+            Icmpv4Type::InformationRequest => (15, 0),
+            Icmpv4Type::InformationReply => (16, 0),
+            Icmpv4Type::AddressMaskRequest => (17, 0),
+            Icmpv4Type::AddressMaskReply => (18, 0),
+            Icmpv4Type::Unknown { typ, code } => (typ, code),
+        }
+    }
+
+    /// Whether this is an error message, which quotes the offending datagram.
+    pub fn is_error(self) -> bool {
+        matches!(
+            self,
+            Icmpv4Type::DestinationUnreachable { .. }
+                | Icmpv4Type::SourceQuench
+                | Icmpv4Type::Redirect { .. }
+                | Icmpv4Type::TimeExceeded { .. }
+                | Icmpv4Type::ParameterProblem { .. }
+        )
+    }
+}
+
+/// ICMPv6 message types, with the same lossless conversion as `Icmpv4Type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Icmpv6Type {
+    DestinationUnreachable { code: Unreachable6Code },
+    PacketTooBig,
+    TimeExceeded { code: TimeExceededCode },
+    ParameterProblem { code: ParameterProblem6Code },
+    EchoRequest,
+    EchoReply,
+    MulticastListenerQuery,
+    MulticastListenerReport,
+    MulticastListenerDone,
+    RouterSolicitation,
+    RouterAdvertisement,
+    NeighborSolicitation,
+    NeighborAdvertisement,
+    Redirect,
+    Unknown { typ: u8, code: u8 },
+}
+
+impl Icmpv6Type {
+    pub fn from_wire(typ: u8, code: u8) -> Self {
+        match (typ, code) {
+            (1, code) => Icmpv6Type::DestinationUnreachable { code: code.into() },
+            (2, 0) => Icmpv6Type::PacketTooBig,
+            (3, code) => Icmpv6Type::TimeExceeded { code: code.into() },
+            (4, code) => Icmpv6Type::ParameterProblem { code: code.into() },
+            (128, 0) => Icmpv6Type::EchoRequest,
+            (129, 0) => Icmpv6Type::EchoReply,
+            (130, 0) => Icmpv6Type::MulticastListenerQuery,
+            (131, 0) => Icmpv6Type::MulticastListenerReport,
+            (132, 0) => Icmpv6Type::MulticastListenerDone,
+            (133, 0) => Icmpv6Type::RouterSolicitation,
+            (134, 0) => Icmpv6Type::RouterAdvertisement,
+            (135, 0) => Icmpv6Type::NeighborSolicitation,
+            (136, 0) => Icmpv6Type::NeighborAdvertisement,
+            (137, 0) => Icmpv6Type::Redirect,
+            (typ, code) => Icmpv6Type::Unknown { typ, code },
+        }
+    }
+
+    pub fn to_wire(self) -> (u8, u8) {
+        match self {
+            Icmpv6Type::DestinationUnreachable { code } => (1, code.into()),
+            Icmpv6Type::PacketTooBig => (2, 0),
+            Icmpv6Type::TimeExceeded { code } => (3, code.into()),
+            Icmpv6Type::ParameterProblem { code } => (4, code.into()),
+            Icmpv6Type::EchoRequest => (128, 0),
+            Icmpv6Type::EchoReply => (129, 0),
+            Icmpv6Type::MulticastListenerQuery => (130, 0),
+            Icmpv6Type::MulticastListenerReport => (131, 0),
+            Icmpv6Type::MulticastListenerDone => (132, 0),
+            Icmpv6Type::RouterSolicitation => (133, 0),
+            Icmpv6Type::RouterAdvertisement => (134, 0),
+            Icmpv6Type::NeighborSolicitation => (135, 0),
+            Icmpv6Type::NeighborAdvertisement => (136, 0),
+            Icmpv6Type::Redirect => (137, 0),
+            Icmpv6Type::Unknown { typ, code } => (typ, code),
+        }
+    }
+
+    /// Whether this is an error message; RFC 4443 reserves types below 128.
+    pub fn is_error(self) -> bool {
+        self.to_wire().0 < 128
+    }
+}
+
+/// A message type of either ICMP version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Message {
+    V4(Icmpv4Type),
+    V6(Icmpv6Type),
+}
+
+impl Message {
+    pub fn from_wire(ver: Version, typ: u8, code: u8) -> Self {
+        match ver {
+            Version::V4 => Message::V4(Icmpv4Type::from_wire(typ, code)),
+            Version::V6 => Message::V6(Icmpv6Type::from_wire(typ, code)),
+        }
+    }
+
+    pub fn to_wire(self) -> (u8, u8) {
+        match self {
+            Message::V4(msg) => msg.to_wire(),
+            Message::V6(msg) => msg.to_wire(),
+        }
+    }
+
+    #[inline]
+    pub fn version(self) -> Version {
+        match self {
+            Message::V4(_) => Version::V4,
+            Message::V6(_) => Version::V6,
+        }
+    }
+
+    pub fn is_error(self) -> bool {
+        match self {
+            Message::V4(msg) => msg.is_error(),
+            Message::V6(msg) => msg.is_error(),
+        }
+    }
+}
+
+impl From<Icmpv4Type> for Message {
+    fn from(msg: Icmpv4Type) -> Self {
+        Message::V4(msg)
+    }
+}
+
+impl From<Icmpv6Type> for Message {
+    fn from(msg: Icmpv6Type) -> Self {
+        Message::V6(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wire_roundtrip() {
+        for typ in 0..=255 {
+            for code in 0..=255 {
+                assert_eq!(Icmpv4Type::from_wire(typ, code).to_wire(), (typ, code));
+                assert_eq!(Icmpv6Type::from_wire(typ, code).to_wire(), (typ, code));
+            }
+        }
+    }
+
+    #[test]
+    fn typed_codes() {
+        assert_eq!(
+            Icmpv4Type::from_wire(3, 3),
+            Icmpv4Type::DestinationUnreachable {
+                code: UnreachableCode::Port
+            }
+        );
+        assert_eq!(
+            Icmpv6Type::from_wire(1, 4),
+            Icmpv6Type::DestinationUnreachable {
+                code: Unreachable6Code::Port
+            }
+        );
+        assert_eq!(
+            Icmpv4Type::from_wire(0, 1),
+            Icmpv4Type::Unknown { typ: 0, code: 1 }
+        );
+    }
+}
@@ -0,0 +1,434 @@
+use std::{
+    collections::HashMap,
+    fmt, io,
+    net::IpAddr,
+    os::unix::io::{AsRawFd, RawFd},
+    time::{Duration, Instant},
+};
+
+use socket2::SockAddr;
+
+use crate::{invalid_input, resolve, Icmp, Reply, Response, SocketKind, Summary, Version};
+
+/// Most replies taken per `recvmmsg` call.
+const BATCH: usize = 64;
+
+/// A destination of a `MultiPinger`, with its own identifier and sequence.
+#[derive(Debug)]
+pub struct Target {
+    addr: SockAddr,
+    idt: u16,
+    seq: u16,
+    sent: HashMap<u16, (Instant, bool)>,
+    summary: Summary,
+    error: Option<io::Error>,
+}
+
+impl Target {
+    #[inline]
+    pub fn addr(&self) -> &SockAddr {
+        &self.addr
+    }
+
+    #[inline]
+    pub fn ident(&self) -> u16 {
+        self.idt
+    }
+
+    #[inline]
+    pub fn summary(&self) -> &Summary {
+        &self.summary
+    }
+
+    /// Why the last request that could not be sent failed, if any did.
+    #[inline]
+    pub fn last_error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+}
+
+/// Formats the per-target summary line of fping.
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sum = &self.summary;
+        match self.addr.as_socket() {
+            Some(addr) => write!(f, "{}", addr.ip())?,
+            None => write!(f, "{:?}", self.addr)?,
+        }
+        write!(
+            f,
+            " : xmt/rcv/%loss = {}/{}/{}%",
+            sum.transmitted,
+            sum.received,
+            sum.loss().round()
+        )?;
+        if sum.received + sum.duplicates != 0 {
+            write!(
+                f,
+                ", min/avg/max = {:.3}/{:.3}/{:.3}",
+                sum.min.as_secs_f64() * 1000.0,
+                sum.avg().as_secs_f64() * 1000.0,
+                sum.max.as_secs_f64() * 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The targets of a `MultiPinger` and the requests sent to them, which
+/// replies are matched against apart from the socket.
+#[derive(Debug, Default)]
+struct Roster {
+    targets: Vec<Target>,
+    index: HashMap<(IpAddr, u16), usize>,
+}
+
+impl Roster {
+    fn add(&mut self, addr: SockAddr, idt: u16) -> io::Result<usize> {
+        let ip = match addr.as_socket() {
+            Some(addr) => addr.ip(),
+            None => return Err(invalid_input("target is not an IP address")),
+        };
+        if self.index.contains_key(&(ip, idt)) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "duplicate target",
+            ));
+        }
+
+        let i = self.targets.len();
+        self.index.insert((ip, idt), i);
+        self.targets.push(Target {
+            addr,
+            idt,
+            seq: 0,
+            sent: HashMap::new(),
+            summary: Summary::default(),
+            error: None,
+        });
+        Ok(i)
+    }
+
+    /// Records that the next request to target `i` went out at `at`.
+    fn sent(&mut self, i: usize, at: Instant, start: Instant) {
+        let target = &mut self.targets[i];
+        target.sent.insert(target.seq, (at, false));
+        target.seq = target.seq.wrapping_add(1);
+        target.summary.transmitted += 1;
+        target.summary.time = at - start;
+    }
+
+    /// Records that the next request to target `i` could not be sent, which
+    /// counts it as transmitted and lost.
+    fn failed(&mut self, i: usize, err: io::Error, at: Instant, start: Instant) {
+        let target = &mut self.targets[i];
+        target.seq = target.seq.wrapping_add(1);
+        target.summary.transmitted += 1;
+        target.summary.errors += 1;
+        target.summary.time = at - start;
+        target.error = Some(err);
+    }
+
+    /// Matches a reply to its target by source and identifier and to its
+    /// request by sequence, and records it. Returns `None` for replies to
+    /// anything else. The data is left unchecked.
+    fn record(
+        &mut self,
+        len: usize,
+        addr: SockAddr,
+        resp: Response,
+        timeout: Duration,
+    ) -> Option<(usize, Reply)> {
+        let src = addr.as_socket()?.ip();
+        let i = *self.index.get(&(src, resp.ident()))?;
+        let target = &mut self.targets[i];
+        let (at, acked) = target.sent.get_mut(&resp.sequence())?;
+        let rtt = resp.rtt().unwrap_or_else(|| at.elapsed());
+        let dup = *acked;
+        let late = rtt > timeout;
+        *acked = true;
+
+        target.summary.record(rtt);
+        if dup {
+            target.summary.duplicates += 1;
+        } else {
+            target.summary.received += 1;
+        }
+        if late {
+            target.summary.late += 1;
+        }
+        let reply = Reply {
+            len,
+            addr,
+            resp,
+            rtt,
+            dup,
+            late,
+            corrupt: None,
+        };
+        Some((i, reply))
+    }
+
+    fn all_acked(&self) -> bool {
+        self.targets
+            .iter()
+            .all(|target| target.sent.values().all(|&(_, acked)| acked))
+    }
+}
+
+/// Pings many destinations over a single socket. Raw sockets give every
+/// target its own identifier, counting up from the base one; ping sockets
+/// share the kernel-assigned identifier and replies are told apart by source
+/// address.
+#[derive(Debug)]
+pub struct MultiPinger {
+    icmp: Icmp,
+    roster: Roster,
+    pub(crate) count: u64,
+    pub(crate) interval: Duration,
+    pub(crate) timeout: Duration,
+    send_buf: Vec<u8>,
+    recv_buf: Vec<u8>,
+}
+
+impl MultiPinger {
+    pub fn new(kind: SocketKind, ver: Version, idt: u16, len: Option<usize>) -> io::Result<Self> {
+        Ok(Self {
+            icmp: Icmp::open(kind, ver, idt, len)?,
+            roster: Roster::default(),
+            count: 1,
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_millis(500),
+            send_buf: Vec::new(),
+            recv_buf: Vec::new(),
+        })
+    }
+
+    /// Number of echo requests sent to each target.
+    pub fn count(mut self, count: u64) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Time between rounds of requests to all targets.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// How long to wait for stragglers after the last round.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Resolves `dst` and adds its first address that has a route as a
+    /// target, returning its index.
+    pub fn add_target(&mut self, dst: &str) -> io::Result<usize> {
+        let addr = resolve::try_each(resolve(self.icmp.ver, dst)?, Ok)?;
+        self.add_addr(addr.into())
+    }
+
+    /// Adds `addr` as a target, returning its index. It must be of the
+    /// `Version` of the pinger.
+    pub fn add_addr(&mut self, addr: SockAddr) -> io::Result<usize> {
+        match addr.as_socket() {
+            Some(sa) if Version::of(&sa.ip()) == self.icmp.ver => {}
+            _ => return Err(invalid_input("target address family does not match")),
+        }
+        let idt = match self.icmp.kind {
+            SocketKind::Raw => self.icmp.idt.wrapping_add(self.roster.targets.len() as u16),
+            _ => self.icmp.idt,
+        };
+        self.roster.add(addr, idt)
+    }
+
+    #[inline]
+    pub fn targets(&self) -> &[Target] {
+        &self.roster.targets
+    }
+
+    #[inline]
+    pub fn icmp_mut(&mut self) -> &mut Icmp {
+        &mut self.icmp
+    }
+
+    /// Runs all rounds, calling `on_reply` with the target index of every reply.
+    /// Each round goes out, and replies come in, batched over as few system
+    /// calls as possible.
+    pub fn run<F: FnMut(usize, &Reply)>(&mut self, mut on_reply: F) -> io::Result<()> {
+        let start = Instant::now();
+        let mut next = start;
+
+        for round in 0..self.count {
+            self.send_round(start);
+            next += self.interval;
+            let last = round + 1 == self.count;
+            let until = if last {
+                Instant::now() + self.timeout
+            } else {
+                next
+            };
+            while !(last && self.all_acked()) {
+                match self.recv_round(Some(until), &mut on_reply) {
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => break,
+                    res => res?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether every request sent so far has been answered.
+    pub(crate) fn all_acked(&self) -> bool {
+        self.roster.all_acked()
+    }
+
+    /// Sends the next request to every target. Requests the kernel refuses
+    /// count as transmitted and lost, and their error is kept on the target.
+    pub(crate) fn send_round(&mut self, start: Instant) {
+        let pkts: Vec<_> = self
+            .roster
+            .targets
+            .iter()
+            .map(|target| (&target.addr, target.idt, target.seq))
+            .collect();
+        let errs = self.icmp.send_batch_to(&pkts, &mut self.send_buf);
+        let n = pkts.len();
+
+        let now = Instant::now();
+        let mut errs = errs.into_iter().peekable();
+        for i in 0..n {
+            match errs.next_if(|&(j, _)| j == i) {
+                Some((_, err)) => self.roster.failed(i, err, now, start),
+                None => self.roster.sent(i, now, start),
+            }
+        }
+    }
+
+    /// Receives one batch of replies, waiting until `deadline` for the first.
+    pub(crate) fn recv_round<F: FnMut(usize, &Reply)>(
+        &mut self,
+        deadline: Option<Instant>,
+        on_reply: &mut F,
+    ) -> io::Result<()> {
+        let ver = self.icmp.ver;
+        let batch = self.icmp.recv_batch_filter(
+            deadline,
+            self.roster.targets.len().min(BATCH),
+            &mut self.recv_buf,
+            |resp| resp.kind() == ver.echo_reply(),
+        )?;
+        for (len, addr, resp) in batch {
+            if let Some((i, mut reply)) = self.roster.record(len, addr, resp, self.timeout) {
+                reply.corrupt = self.icmp.verify_data(&reply.resp.view()).err();
+                on_reply(i, &reply);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for MultiPinger {
+    fn as_raw_fd(&self) -> RawFd {
+        self.icmp.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum;
+    use std::net::SocketAddr;
+
+    fn reply(idt: u16, seq: u16) -> Response {
+        let mut msg = vec![0, 0, 0, 0];
+        msg.extend_from_slice(&idt.to_be_bytes());
+        msg.extend_from_slice(&seq.to_be_bytes());
+        let sum = checksum(&msg);
+        msg[2..4].copy_from_slice(&sum.to_be_bytes());
+        Response::decode_icmp(Version::V4, &msg, 64).unwrap()
+    }
+
+    fn from(ip: &str) -> SockAddr {
+        SocketAddr::new(ip.parse().unwrap(), 0).into()
+    }
+
+    #[test]
+    fn record_matches_source_ident_and_seq() {
+        let mut roster = Roster::default();
+        let start = Instant::now();
+        assert_eq!(roster.add(from("10.0.0.1"), 100).unwrap(), 0);
+        assert_eq!(roster.add(from("10.0.0.2"), 101).unwrap(), 1);
+        assert!(roster.add(from("10.0.0.2"), 101).is_err());
+        roster.sent(0, start, start);
+        roster.sent(1, start, start);
+        let timeout = Duration::from_secs(1);
+
+        let (i, first) = roster
+            .record(28, from("10.0.0.1"), reply(100, 0), timeout)
+            .unwrap();
+        assert_eq!(i, 0);
+        assert!(!first.dup && !first.late);
+        assert!(!roster.all_acked());
+
+        // Another target's identifier, an unknown source, a sequence never
+        // sent and a reply to a request not yet sent to this target.
+        for (src, idt, seq) in [
+            ("10.0.0.2", 100, 0),
+            ("10.0.0.3", 100, 0),
+            ("10.0.0.2", 101, 7),
+            ("10.0.0.1", 100, 1),
+        ] {
+            assert!(roster
+                .record(28, from(src), reply(idt, seq), timeout)
+                .is_none());
+        }
+
+        let (_, dup) = roster
+            .record(28, from("10.0.0.1"), reply(100, 0), timeout)
+            .unwrap();
+        assert!(dup.dup);
+        let (i, _) = roster
+            .record(28, from("10.0.0.2"), reply(101, 0), timeout)
+            .unwrap();
+        assert_eq!(i, 1);
+        assert!(roster.all_acked());
+
+        let sum = &roster.targets[0].summary;
+        assert_eq!((sum.transmitted, sum.received, sum.duplicates), (1, 1, 1));
+    }
+
+    #[test]
+    fn failed_sends_count_as_lost() {
+        let mut roster = Roster::default();
+        let start = Instant::now();
+        roster.add(from("10.0.0.1"), 100).unwrap();
+        roster.failed(
+            0,
+            io::Error::from_raw_os_error(libc::ENETUNREACH),
+            start,
+            start,
+        );
+        roster.sent(0, start, start);
+
+        // The failed request used up sequence 0.
+        let timeout = Duration::from_secs(1);
+        assert!(roster
+            .record(28, from("10.0.0.1"), reply(100, 0), timeout)
+            .is_none());
+        assert!(roster
+            .record(28, from("10.0.0.1"), reply(100, 1), timeout)
+            .is_some());
+        assert!(roster.all_acked());
+
+        let target = &roster.targets[0];
+        let sum = &target.summary;
+        assert_eq!((sum.transmitted, sum.received, sum.errors), (2, 1, 1));
+        assert_eq!(sum.lost(), 1);
+        assert_eq!(
+            target.last_error().and_then(|e| e.raw_os_error()),
+            Some(libc::ENETUNREACH)
+        );
+    }
+}
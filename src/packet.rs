@@ -0,0 +1,226 @@
+use std::{io, net::Ipv6Addr};
+
+use crate::{
+    checksum, checksum_v6, Icmpv4Type, Icmpv6Type, Message, Unreachable6Code, UnreachableCode,
+    Version,
+};
+
+/// Most of the offending datagram an ICMPv4 error quotes, keeping the whole
+/// packet within the 576 bytes every host must accept (RFC 1812, 4.3.2.3).
+const QUOTELEN_V4: usize = 576 - 20 - 8;
+/// Likewise for ICMPv6, within the minimum MTU (RFC 4443, section 2.4).
+const QUOTELEN_V6: usize = 1280 - 40 - 8;
+
+/// Builds an arbitrary ICMPv4 or ICMPv6 message, for `Icmp::send_packet` or
+/// for serializing with `write_to`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IcmpPacketBuilder {
+    msg: Message,
+    hdr: [u8; 4],
+    dat: Vec<u8>,
+    addrs: Option<(Ipv6Addr, Ipv6Addr)>,
+}
+
+impl IcmpPacketBuilder {
+    /// Starts a message of any kind, with the rest of the header zeroed and no
+    /// data.
+    pub fn new<M: Into<Message>>(msg: M) -> Self {
+        Self {
+            msg: msg.into(),
+            hdr: [0; 4],
+            dat: Vec::new(),
+            addrs: None,
+        }
+    }
+
+    pub fn echo(ver: Version, idt: u16, seq: u16) -> Self {
+        let msg = match ver {
+            Version::V4 => Message::V4(Icmpv4Type::Echo),
+            Version::V6 => Message::V6(Icmpv6Type::EchoRequest),
+        };
+        Self::new(msg).ident(idt).sequence(seq)
+    }
+
+    /// An ICMPv4 Timestamp request, `originate` being milliseconds since
+    /// midnight UTC. The receive and transmit timestamps are left zero.
+    pub fn timestamp(idt: u16, seq: u16, originate: u32) -> Self {
+        let mut dat = [0; 12];
+        dat[..4].copy_from_slice(&originate.to_be_bytes());
+        Self::new(Icmpv4Type::Timestamp)
+            .ident(idt)
+            .sequence(seq)
+            .data(&dat)
+    }
+
+    /// An ICMPv4 Address Mask request, with a zero mask.
+    pub fn address_mask(idt: u16, seq: u16) -> Self {
+        Self::new(Icmpv4Type::AddressMaskRequest)
+            .ident(idt)
+            .sequence(seq)
+            .data(&[0; 4])
+    }
+
+    pub fn router_solicitation(ver: Version) -> Self {
+        match ver {
+            Version::V4 => Self::new(Icmpv4Type::RouterSolicitation),
+            Version::V6 => Self::new(Icmpv6Type::RouterSolicitation),
+        }
+    }
+
+    /// An ICMPv6 Neighbor Solicitation for `target`. Receivers drop it unless
+    /// it is sent with a hop limit of 255.
+    pub fn neighbor_solicitation(target: Ipv6Addr) -> Self {
+        Self::new(Icmpv6Type::NeighborSolicitation).data(&target.octets())
+    }
+
+    /// An ICMPv4 Destination Unreachable error quoting `datagram`, the
+    /// offending packet starting with its IP header.
+    pub fn dest_unreachable(code: UnreachableCode, datagram: &[u8]) -> Self {
+        Self::new(Icmpv4Type::DestinationUnreachable { code }).quote(datagram)
+    }
+
+    /// An ICMPv6 Destination Unreachable error quoting `datagram`.
+    pub fn dest_unreachable_v6(code: Unreachable6Code, datagram: &[u8]) -> Self {
+        Self::new(Icmpv6Type::DestinationUnreachable { code }).quote(datagram)
+    }
+
+    /// Sets the first half of the rest of the header, the identifier of
+    /// queries.
+    pub fn ident(mut self, idt: u16) -> Self {
+        self.hdr[..2].copy_from_slice(&idt.to_be_bytes());
+        self
+    }
+
+    /// Sets the second half of the rest of the header, the sequence of
+    /// queries.
+    pub fn sequence(mut self, seq: u16) -> Self {
+        self.hdr[2..].copy_from_slice(&seq.to_be_bytes());
+        self
+    }
+
+    /// Sets the whole rest of the header, such as the next-hop MTU of
+    /// Fragmentation Needed or Packet Too Big, or a Parameter Problem pointer.
+    pub fn rest(mut self, rest: u32) -> Self {
+        self.hdr = rest.to_be_bytes();
+        self
+    }
+
+    /// Replaces the data following the header.
+    pub fn data(mut self, dat: &[u8]) -> Self {
+        self.dat = dat.to_vec();
+        self
+    }
+
+    /// Appends as much of `datagram` as an error message may quote.
+    pub fn quote(mut self, datagram: &[u8]) -> Self {
+        let max = match self.msg.version() {
+            Version::V4 => QUOTELEN_V4,
+            Version::V6 => QUOTELEN_V6,
+        };
+        let len = datagram.len().min(max.saturating_sub(self.dat.len()));
+        self.dat.extend_from_slice(&datagram[..len]);
+        self
+    }
+
+    /// Appends a Neighbor Discovery option, padded to a multiple of 8 bytes
+    /// (RFC 4861, section 4.6).
+    pub fn option(mut self, typ: u8, val: &[u8]) -> Self {
+        let len = (2 + val.len()).div_ceil(8);
+        self.dat.push(typ);
+        self.dat.push(len as u8);
+        self.dat.extend_from_slice(val);
+        self.dat.resize(self.dat.len() + 8 * len - 2 - val.len(), 0);
+        self
+    }
+
+    /// Computes the ICMPv6 checksum over the pseudo-header of `src` and
+    /// `dst`. Without it the checksum is left zero, which raw ICMPv6 sockets
+    /// fill in on sending.
+    pub fn addrs(mut self, src: Ipv6Addr, dst: Ipv6Addr) -> Self {
+        self.addrs = Some((src, dst));
+        self
+    }
+
+    #[inline]
+    pub fn message(&self) -> Message {
+        self.msg
+    }
+
+    #[inline]
+    pub fn version(&self) -> Version {
+        self.msg.version()
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    #[inline]
+    pub fn len(&self) -> usize {
+        8 + self.dat.len()
+    }
+
+    /// Serializes the message into `buf`, returning its length. Fails with
+    /// `io::ErrorKind::InvalidInput` if `buf` is shorter than `len`.
+    pub fn write_to(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let buf = buf
+            .get_mut(..self.len())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "buffer too small"))?;
+        let (typ, code) = self.msg.to_wire();
+        buf[0] = typ;
+        buf[1] = code;
+        buf[2..4].fill(0);
+        buf[4..8].copy_from_slice(&self.hdr);
+        buf[8..].copy_from_slice(&self.dat);
+
+        let sum = match (self.msg.version(), self.addrs) {
+            (Version::V4, _) => checksum(buf),
+            (Version::V6, Some((src, dst))) => checksum_v6(&src, &dst, buf),
+            (Version::V6, None) => 0,
+        };
+        buf[2..4].copy_from_slice(&sum.to_be_bytes());
+        Ok(buf.len())
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut buf = vec![0; self.len()];
+        self.write_to(&mut buf).unwrap();
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseRef;
+
+    #[test]
+    fn checksums() {
+        let pkt = IcmpPacketBuilder::timestamp(7, 1, 1234).build();
+        let resp = ResponseRef::decode_icmp(Version::V4, &pkt, 64).unwrap();
+        assert_eq!(resp.message(), Message::V4(Icmpv4Type::Timestamp));
+        assert_eq!((resp.ident(), resp.sequence()), (7, 1));
+        assert_eq!(&resp.data()[..4], &1234u32.to_be_bytes());
+        assert!(resp.verify_checksum());
+
+        let (src, dst) = (
+            "fe80::1".parse().unwrap(),
+            "ff02::1:ff00:2".parse().unwrap(),
+        );
+        let pkt = IcmpPacketBuilder::neighbor_solicitation("fe80::2".parse().unwrap())
+            .addrs(src, dst)
+            .build();
+        let resp = ResponseRef::decode_icmp(Version::V6, &pkt, 255).unwrap();
+        assert_eq!(resp.kind(), 135);
+        assert!(resp.verify_checksum_v6(&src, &dst));
+    }
+
+    #[test]
+    fn quote_and_options() {
+        let pkt = IcmpPacketBuilder::dest_unreachable(UnreachableCode::Port, &[0x45; 1000]);
+        assert_eq!(pkt.len(), 576 - 20);
+
+        let pkt = IcmpPacketBuilder::neighbor_solicitation(Ipv6Addr::LOCALHOST)
+            .option(1, &[0x02, 0, 0, 0, 0, 1])
+            .build();
+        assert_eq!(pkt.len(), 8 + 16 + 8);
+        assert_eq!(&pkt[24..], &[1, 1, 0x02, 0, 0, 0, 0, 1]);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::sys;
+
+/// What fills the data of echo requests, see `Icmp::set_payload`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Payload {
+    #[default]
+    Zeros,
+    /// Bytes repeated over the whole data, like the `-p` option of ping.
+    Pattern(Vec<u8>),
+    /// Each byte holds its offset, wrapping at 256, as BSD ping sends.
+    Incrementing,
+    /// Pseudo-random bytes, drawn once when the payload is set.
+    Random,
+    /// Exactly these bytes; setting them also sets the data length.
+    Bytes(Vec<u8>),
+}
+
+impl Payload {
+    /// Parses the hex pattern of ping `-p`, up to 16 bytes such as `ff00`. An
+    /// odd trailing digit stands for a byte of its own, as in iputils.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if hex.is_empty() || hex.len() > 32 || !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        hex.as_bytes()
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+            .collect::<Option<_>>()
+            .map(Payload::Pattern)
+    }
+
+    /// Fills `dat[from..]` with the bytes due at those offsets.
+    pub(crate) fn fill(&self, dat: &mut [u8], from: usize) {
+        let tail = &mut dat[from..];
+        match self {
+            Payload::Zeros => tail.fill(0),
+            Payload::Pattern(pat) if pat.is_empty() => tail.fill(0),
+            Payload::Pattern(pat) => {
+                for (i, b) in tail.iter_mut().enumerate() {
+                    *b = pat[(from + i) % pat.len()];
+                }
+            }
+            Payload::Incrementing => {
+                for (i, b) in tail.iter_mut().enumerate() {
+                    *b = (from + i) as u8;
+                }
+            }
+            Payload::Random => {
+                // xorshift64*, which is plenty to defeat link compression.
+                let mut state = sys::realtime().as_nanos() as u64 | 1;
+                for b in tail.iter_mut() {
+                    state ^= state >> 12;
+                    state ^= state << 25;
+                    state ^= state >> 27;
+                    *b = (state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 56) as u8;
+                }
+            }
+            Payload::Bytes(bytes) => {
+                for (i, b) in tail.iter_mut().enumerate() {
+                    *b = bytes.get(from + i).copied().unwrap_or(0);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_patterns() {
+        let mut dat = [9; 6];
+        Payload::Pattern(vec![0xab, 0xcd]).fill(&mut dat, 1);
+        assert_eq!(dat, [9, 0xcd, 0xab, 0xcd, 0xab, 0xcd]);
+        Payload::Incrementing.fill(&mut dat, 0);
+        assert_eq!(dat, [0, 1, 2, 3, 4, 5]);
+        Payload::Bytes(vec![7, 8]).fill(&mut dat, 0);
+        assert_eq!(dat, [7, 8, 0, 0, 0, 0]);
+
+        assert_eq!(
+            Payload::from_hex("ff00a"),
+            Some(Payload::Pattern(vec![0xff, 0, 0xa]))
+        );
+        assert_eq!(Payload::from_hex("xyz"), None);
+        assert_eq!(Payload::from_hex(""), None);
+    }
+}
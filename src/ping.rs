@@ -0,0 +1,367 @@
+use std::{
+    collections::HashMap,
+    fmt, io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use socket2::SockAddr;
+
+use crate::{DataError, Icmp, Response, SeqStatus, SeqTracker};
+
+/// Requests older than this many sends count as lost, so that replies to
+/// them cannot be mistaken for replies to their namesakes after the
+/// sequence wraps around.
+const WINDOW: u16 = 4096;
+
+/// Longest a `Pinger` with a stop flag waits before checking it again.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// A reply as seen by a `Pinger`. `late` replies arrived after the per-packet
+/// timeout but are still counted as received, as iputils ping does, and so are
+/// replies whose data was `corrupt`ed or truncated on the way.
+#[derive(Debug)]
+pub struct Reply {
+    pub len: usize,
+    pub addr: SockAddr,
+    pub resp: Response,
+    pub rtt: Duration,
+    pub dup: bool,
+    pub late: bool,
+    pub corrupt: Option<DataError>,
+}
+
+/// Session statistics with the semantics of iputils ping: duplicates are not
+/// counted as received but do contribute to the RTT figures.
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    pub transmitted: u64,
+    pub received: u64,
+    pub duplicates: u64,
+    pub late: u64,
+    /// Requests that could not be sent, which count as transmitted and lost.
+    pub errors: u64,
+    pub time: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    tsum: f64,
+    tsum2: f64,
+}
+
+impl Summary {
+    pub(crate) fn record(&mut self, rtt: Duration) {
+        let n = self.received + self.duplicates;
+        if n == 0 || rtt < self.min {
+            self.min = rtt;
+        }
+        if rtt > self.max {
+            self.max = rtt;
+        }
+        let t = rtt.as_secs_f64();
+        self.tsum += t;
+        self.tsum2 += t * t;
+    }
+
+    #[inline]
+    pub fn lost(&self) -> u64 {
+        self.transmitted.saturating_sub(self.received)
+    }
+
+    /// Packet loss in percent.
+    pub fn loss(&self) -> f64 {
+        match self.transmitted {
+            0 => 0.0,
+            n => self.lost() as f64 * 100.0 / n as f64,
+        }
+    }
+
+    pub fn avg(&self) -> Duration {
+        match self.received + self.duplicates {
+            0 => Duration::ZERO,
+            n => Duration::from_secs_f64(self.tsum / n as f64),
+        }
+    }
+
+    pub fn mdev(&self) -> Duration {
+        match self.received + self.duplicates {
+            0 => Duration::ZERO,
+            n => {
+                let avg = self.tsum / n as f64;
+                let var = self.tsum2 / n as f64 - avg * avg;
+                Duration::from_secs_f64(var.max(0.0).sqrt())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} packets transmitted, {} received",
+            self.transmitted, self.received
+        )?;
+        if self.duplicates != 0 {
+            write!(f, ", +{} duplicates", self.duplicates)?;
+        }
+        if self.errors != 0 {
+            write!(f, ", +{} errors", self.errors)?;
+        }
+        write!(
+            f,
+            ", {}% packet loss, time {}ms",
+            fmt_g(self.loss()),
+            self.time.as_millis()
+        )?;
+        if self.received + self.duplicates != 0 {
+            write!(
+                f,
+                "\nrtt min/avg/max/mdev = {:.3}/{:.3}/{:.3}/{:.3} ms",
+                ms(self.min),
+                ms(self.avg()),
+                ms(self.max),
+                ms(self.mdev())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/// Formats like C's `%g`, which ping uses for the loss percentage.
+fn fmt_g(v: f64) -> String {
+    let int = (v.abs().trunc() as u64).to_string().len();
+    let s = format!("{:.*}", 6usize.saturating_sub(int), v);
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        s
+    }
+}
+
+/// Drives a ping session over an `Icmp`: sends echo requests at an interval,
+/// matches replies by sequence and gathers a `Summary`.
+#[derive(Debug)]
+pub struct Pinger {
+    icmp: Icmp,
+    count: Option<u64>,
+    interval: Duration,
+    timeout: Duration,
+    deadline: Option<Duration>,
+    flood: bool,
+    stop: Option<Arc<AtomicBool>>,
+}
+
+impl Pinger {
+    pub fn new(icmp: Icmp) -> Self {
+        Self {
+            icmp,
+            count: None,
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(10),
+            deadline: None,
+            flood: false,
+            stop: None,
+        }
+    }
+
+    /// Number of echo requests to send; `None` pings forever.
+    pub fn count(mut self, count: Option<u64>) -> Self {
+        self.count = count;
+        self
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// How long to wait for each reply, and for stragglers after the last send.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Ends the session this long after it started, however many requests
+    /// were sent. With a `count` as well, the session keeps sending until that
+    /// many replies arrive or the deadline passes, as `ping -w` does.
+    pub fn deadline(mut self, deadline: Option<Duration>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Sends the next request as soon as the last one is answered rather than
+    /// waiting out the interval, which still paces unanswered requests.
+    pub fn flood(mut self, flood: bool) -> Self {
+        self.flood = flood;
+        self
+    }
+
+    /// Ends the session early once `stop` is set, such as from a signal
+    /// handler. It is checked at least every 100 ms.
+    pub fn stop_on(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    #[inline]
+    pub fn icmp(&self) -> &Icmp {
+        &self.icmp
+    }
+
+    #[inline]
+    pub fn icmp_mut(&mut self) -> &mut Icmp {
+        &mut self.icmp
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> Icmp {
+        self.icmp
+    }
+
+    /// Runs the session, calling `on_reply` for every reply received.
+    pub fn run<F: FnMut(&Reply)>(&mut self, on_reply: F) -> io::Result<Summary> {
+        self.run_with(|_| {}, on_reply)
+    }
+
+    /// Like `run`, also calling `on_send` with the sequence of every request
+    /// sent.
+    pub fn run_with<S, F>(&mut self, mut on_send: S, mut on_reply: F) -> io::Result<Summary>
+    where
+        S: FnMut(u16),
+        F: FnMut(&Reply),
+    {
+        let mut summary = Summary::default();
+        let mut sent: HashMap<u16, (Instant, bool)> = HashMap::new();
+        let mut tracker = SeqTracker::new(WINDOW);
+        let start = Instant::now();
+        let end = self.deadline.map(|deadline| start + deadline);
+        let mut next = start;
+        let mut last = start;
+        let mut newest = None;
+
+        loop {
+            let done = match (self.count, end) {
+                (Some(count), Some(_)) => summary.received >= count,
+                (Some(count), None) => summary.transmitted >= count,
+                (None, _) => false,
+            };
+            let now = Instant::now();
+            let stopped = self
+                .stop
+                .as_ref()
+                .is_some_and(|stop| stop.load(Ordering::Relaxed));
+            if stopped || end.is_some_and(|end| now >= end) {
+                break;
+            }
+            if done {
+                let pending = sent.values().any(|&(_, acked)| !acked);
+                if !pending || now >= last + self.timeout {
+                    break;
+                }
+            } else if now >= next {
+                let seq = self.icmp.send()?;
+                sent.insert(seq, (now, false));
+                tracker.sent(seq);
+                summary.transmitted += 1;
+                on_send(seq);
+                newest = Some(seq);
+                last = now;
+                next = match self.flood {
+                    // Do not try to catch up on a slow path.
+                    true => now + self.interval,
+                    false => next + self.interval,
+                };
+                continue;
+            }
+
+            let mut until = if done { last + self.timeout } else { next };
+            if let Some(end) = end {
+                until = until.min(end);
+            }
+            if self.stop.is_some() {
+                until = until.min(now + STOP_POLL);
+            }
+            let (len, addr, resp) = match self.icmp.recv_until(until) {
+                Ok(reply) => reply,
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                Err(e) => return Err(e),
+            };
+            let status = tracker.classify(resp.sequence());
+            let (at, acked) = match sent.get_mut(&resp.sequence()) {
+                Some(entry) if status != SeqStatus::Stale => entry,
+                _ => continue,
+            };
+            let rtt = resp.rtt().unwrap_or_else(|| at.elapsed());
+            let dup = status == SeqStatus::Duplicate;
+            let late = rtt > self.timeout;
+            let corrupt = self.icmp.verify_data(&resp.view()).err();
+            *acked = true;
+            if self.flood && !dup && newest == Some(resp.sequence()) {
+                next = next.min(Instant::now());
+            }
+
+            summary.record(rtt);
+            if dup {
+                summary.duplicates += 1;
+            } else {
+                summary.received += 1;
+            }
+            if late {
+                summary.late += 1;
+            }
+            on_reply(&Reply {
+                len,
+                addr,
+                resp,
+                rtt,
+                dup,
+                late,
+                corrupt,
+            });
+        }
+
+        summary.time = last - start;
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_stats() {
+        let mut summary = Summary {
+            transmitted: 4,
+            ..Default::default()
+        };
+        for ms in &[10, 20, 30] {
+            summary.record(Duration::from_millis(*ms));
+            summary.received += 1;
+        }
+        summary.record(Duration::from_millis(20));
+        summary.duplicates += 1;
+
+        assert_eq!(summary.lost(), 1);
+        assert_eq!(summary.loss(), 25.0);
+        assert_eq!(summary.min, Duration::from_millis(10));
+        assert_eq!(summary.max, Duration::from_millis(30));
+        assert_eq!(summary.avg().as_micros(), 20_000);
+        assert_eq!(summary.mdev().as_micros(), 7_071);
+    }
+
+    #[test]
+    fn loss_format() {
+        assert_eq!(fmt_g(0.0), "0");
+        assert_eq!(fmt_g(100.0), "100");
+        assert_eq!(fmt_g(12.5), "12.5");
+        assert_eq!(fmt_g(100.0 / 3.0), "33.3333");
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use crate::{Icmp, SocketKind, Version};
+
+/// What became of a probe of a given size.
+enum Outcome {
+    Fits,
+    /// Too big for some link, with the MTU of that link if it was advertised.
+    TooBig(Option<usize>),
+}
+
+/// Binary-searches `min..=max` for the largest MTU that `probe` finds fits,
+/// jumping straight to any smaller MTU a too-big answer advertises. `EMSGSIZE`
+/// from `probe` counts as too big.
+fn search<F>(min: usize, max: usize, mut probe: F) -> io::Result<usize>
+where
+    F: FnMut(usize) -> io::Result<Outcome>,
+{
+    let (mut lo, mut hi) = (min, max.max(min));
+    let mut next = hi;
+
+    while lo < hi {
+        let outcome = match probe(next) {
+            // Larger than the MTU of our own interface.
+            Err(e) if e.raw_os_error() == Some(libc::EMSGSIZE) => Outcome::TooBig(None),
+            res => res?,
+        };
+        match outcome {
+            Outcome::Fits => lo = next,
+            Outcome::TooBig(Some(mtu)) if mtu > lo && mtu < next => {
+                hi = mtu;
+                next = mtu;
+                continue;
+            }
+            Outcome::TooBig(_) => hi = next - 1,
+        }
+        next = (lo + hi).div_ceil(2);
+    }
+    Ok(lo)
+}
+
+/// Discovers the path MTU to the destination of an `Icmp` by binary-searching
+/// the size of echo requests sent with Don't Fragment set. Fragmentation
+/// Needed and Packet Too Big errors narrow the search to the MTU they
+/// advertise; probes that go unanswered count as too big, as routers that
+/// drop them silently are common. The errors are read as they arrive on a raw
+/// socket; ping sockets would need them taken from the error queue, which
+/// this implementation does not do, so they are not supported.
+#[derive(Debug)]
+pub struct PathMtu {
+    icmp: Icmp,
+    min: usize,
+    max: usize,
+    retries: usize,
+    timeout: Duration,
+}
+
+impl PathMtu {
+    pub fn new(icmp: Icmp) -> io::Result<Self> {
+        if icmp.kind != SocketKind::Raw {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "path MTU discovery needs a raw socket",
+            ));
+        }
+        icmp.set_dont_fragment(true)?;
+        let min = match icmp.ver {
+            Version::V4 => 68,
+            Version::V6 => 1280,
+        };
+        Ok(Self {
+            icmp,
+            min,
+            max: 1500,
+            retries: 2,
+            timeout: Duration::from_secs(1),
+        })
+    }
+
+    /// Smallest MTU considered, assumed to get through. Defaults to the
+    /// minimum every link must support: 68 bytes for IPv4, 1280 for IPv6.
+    pub fn min(mut self, mtu: usize) -> Self {
+        self.min = mtu;
+        self
+    }
+
+    /// Largest MTU considered, 1500 by default.
+    pub fn max(mut self, mtu: usize) -> Self {
+        self.max = mtu;
+        self
+    }
+
+    /// Number of times an unanswered probe is resent before it counts as too
+    /// big.
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// How long to wait for the answer to each probe.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> Icmp {
+        self.icmp
+    }
+
+    /// Runs the search, returning the path MTU. The data length of the `Icmp`
+    /// is put back afterwards.
+    pub fn run(&mut self) -> io::Result<usize> {
+        let len = self.icmp.dat.len();
+        let res = search(self.min, self.max, |mtu| self.probe(mtu));
+        self.icmp.set_data_len(len);
+        res
+    }
+
+    fn probe(&mut self, mtu: usize) -> io::Result<Outcome> {
+        let hdr_len = match self.icmp.ver {
+            Version::V4 => 20,
+            Version::V6 => 40,
+        };
+        self.icmp.set_data_len(mtu.saturating_sub(hdr_len + 8));
+
+        for _ in 0..=self.retries {
+            let (ver, idt) = (self.icmp.ver, self.icmp.idt);
+            let seq = self.icmp.send()?;
+
+            let res = self
+                .icmp
+                .recv_filter(Some(Instant::now() + self.timeout), |resp| {
+                    if resp.kind() == ver.echo_reply() {
+                        resp.ident() == idt && resp.sequence() == seq
+                    } else {
+                        resp.next_hop_mtu().is_some() && resp.quoted_echo() == Some((idt, seq))
+                    }
+                });
+            match res {
+                Ok((_, _, resp)) => {
+                    return Ok(match resp.next_hop_mtu() {
+                        None => Outcome::Fits,
+                        Some(0) => Outcome::TooBig(None),
+                        Some(mtu) => Outcome::TooBig(Some(mtu as usize)),
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Outcome::TooBig(None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Probes a path of links with the given MTUs. The first is that of our
+    /// own interface, which fails the send; the others answer too big, with
+    /// their MTU if `advertise`.
+    fn path(links: &[usize], advertise: bool) -> impl FnMut(usize) -> io::Result<Outcome> + '_ {
+        move |mtu| {
+            Ok(match links.iter().position(|&link| mtu > link) {
+                None => Outcome::Fits,
+                Some(0) => return Err(io::Error::from_raw_os_error(libc::EMSGSIZE)),
+                Some(i) if advertise => Outcome::TooBig(Some(links[i])),
+                Some(_) => Outcome::TooBig(None),
+            })
+        }
+    }
+
+    #[test]
+    fn search_converges() {
+        for mtu in [68, 576, 1280, 1400, 1499, 1500] {
+            for advertise in [false, true] {
+                let links = [[9000, mtu], [mtu, 9000], [1499, mtu]];
+                for links in &links {
+                    let want = *links.iter().min().unwrap();
+                    assert_eq!(search(68, 1500, path(links, advertise)).unwrap(), want);
+                }
+            }
+        }
+
+        // An advertised MTU takes a single probe to confirm.
+        let mut probes = 0;
+        let mut probe = path(&[9000, 1400], true);
+        let found = search(68, 1500, |mtu| {
+            probes += 1;
+            probe(mtu)
+        });
+        assert_eq!((found.unwrap(), probes), (1400, 2));
+
+        // Advertised MTUs outside the search are ignored, and the bounds are
+        // never left.
+        assert_eq!(search(1280, 1500, path(&[9000, 1000], true)).unwrap(), 1280);
+        assert_eq!(
+            search(68, 1500, |_| Ok(Outcome::TooBig(Some(9000)))).unwrap(),
+            68
+        );
+        assert_eq!(
+            search(1500, 1280, |_| Ok(Outcome::TooBig(None))).unwrap(),
+            1500
+        );
+
+        let err = search(68, 1500, |_| Err(io::Error::from_raw_os_error(libc::EPERM)));
+        assert_eq!(err.unwrap_err().raw_os_error(), Some(libc::EPERM));
+    }
+}
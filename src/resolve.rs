@@ -0,0 +1,191 @@
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    io,
+    mem::zeroed,
+    net::{IpAddr, SocketAddr},
+    ptr::{self, copy_nonoverlapping},
+};
+
+use socket2::SockAddr;
+
+use crate::{sys, ResolveError, Version};
+
+/// Turns host names into addresses. `ver` restricts the result to one
+/// family; `None` asks for both.
+pub trait Resolver {
+    fn resolve(&self, host: &str, ver: Option<Version>) -> Result<Vec<SocketAddr>, ResolveError>;
+}
+
+/// Resolves through libc `getaddrinfo`, honouring /etc/hosts, DNS and the
+/// rest of the system configuration.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str, ver: Option<Version>) -> Result<Vec<SocketAddr>, ResolveError> {
+        getaddrinfo(host, ver)
+    }
+}
+
+/// Resolves from a fixed host table, for tests and offline environments.
+/// Address literals resolve to themselves.
+#[derive(Debug, Clone, Default)]
+pub struct StaticResolver {
+    hosts: HashMap<String, Vec<IpAddr>>,
+}
+
+impl StaticResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an address for `host`; earlier addresses rank first.
+    pub fn host(mut self, host: &str, addr: IpAddr) -> Self {
+        self.hosts.entry(host.to_string()).or_default().push(addr);
+        self
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn resolve(&self, host: &str, ver: Option<Version>) -> Result<Vec<SocketAddr>, ResolveError> {
+        let addrs = match host.parse::<IpAddr>() {
+            Ok(addr) => vec![addr],
+            Err(_) => self.hosts.get(host).cloned().ok_or(ResolveError::Lookup {
+                code: libc::EAI_NONAME,
+                msg: "host not in static table".to_string(),
+            })?,
+        };
+        let addrs: Vec<_> = addrs
+            .into_iter()
+            .filter(|addr| ver.map_or(true, |ver| Version::of(addr) == ver))
+            .map(|addr| SocketAddr::new(addr, 0))
+            .collect();
+        if addrs.is_empty() {
+            return Err(ResolveError::NoAddress);
+        }
+        Ok(addrs)
+    }
+}
+
+/// Resolves `host` through libc `getaddrinfo`, returning every address of
+/// the family of `ver` in the order the resolver ranked them.
+pub fn resolve(ver: Version, host: &str) -> Result<Vec<SocketAddr>, ResolveError> {
+    getaddrinfo(host, Some(ver))
+}
+
+/// Orders addresses for trying one after another: alternating families,
+/// IPv6 first, keeping the resolver's ranking within each family (RFC 8305).
+pub(crate) fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (mut v6, mut v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(|addr| addr.is_ipv6());
+    v6.reverse();
+    v4.reverse();
+    let mut out = Vec::with_capacity(v6.len() + v4.len());
+    while !v6.is_empty() || !v4.is_empty() {
+        out.extend(v6.pop());
+        out.extend(v4.pop());
+    }
+    out
+}
+
+/// Tries `addrs` in turn, returning what `f` makes of the first one the
+/// kernel has a route to, or the last error.
+pub(crate) fn try_each<T, F>(addrs: Vec<SocketAddr>, mut f: F) -> io::Result<T>
+where
+    F: FnMut(SocketAddr) -> io::Result<T>,
+{
+    let mut err = None;
+    for addr in addrs {
+        match sys::route_exists(&addr).and_then(|_| f(addr)) {
+            Ok(val) => return Ok(val),
+            Err(e) => err = Some(e),
+        }
+    }
+    Err(err.unwrap_or_else(|| ResolveError::NoAddress.into()))
+}
+
+fn getaddrinfo(host: &str, ver: Option<Version>) -> Result<Vec<SocketAddr>, ResolveError> {
+    let host = CString::new(host).map_err(|_| ResolveError::InvalidHost)?;
+    let mut hints: libc::addrinfo = unsafe { zeroed() };
+    hints.ai_family = match ver {
+        Some(Version::V4) => libc::AF_INET,
+        Some(Version::V6) => libc::AF_INET6,
+        None => libc::AF_UNSPEC,
+    };
+    hints.ai_socktype = libc::SOCK_RAW;
+
+    let mut res = ptr::null_mut();
+    let code = unsafe { libc::getaddrinfo(host.as_ptr(), ptr::null(), &hints, &mut res) };
+    if code != 0 {
+        let msg = match code {
+            libc::EAI_SYSTEM => io::Error::last_os_error().to_string(),
+            _ => unsafe { CStr::from_ptr(libc::gai_strerror(code)) }
+                .to_string_lossy()
+                .into_owned(),
+        };
+        return Err(ResolveError::Lookup { code, msg });
+    }
+
+    let mut addrs = Vec::new();
+    let mut ai = res;
+    while !ai.is_null() {
+        let addr = unsafe {
+            SockAddr::init(|addr, len| {
+                len.write((*ai).ai_addrlen);
+                copy_nonoverlapping(
+                    (*ai).ai_addr.cast::<u8>(),
+                    addr.cast::<u8>(),
+                    (*ai).ai_addrlen as usize,
+                );
+                Ok(())
+            })
+        };
+        if let Some(addr) = addr.ok().and_then(|(_, addr)| addr.as_socket()) {
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+        ai = unsafe { (*ai).ai_next };
+    }
+    unsafe { libc::freeaddrinfo(res) };
+
+    if addrs.is_empty() {
+        return Err(ResolveError::NoAddress);
+    }
+    Ok(addrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn static_table() {
+        let resolver = StaticResolver::new()
+            .host("router", "10.0.0.1".parse().unwrap())
+            .host("router", "fd00::1".parse().unwrap());
+
+        let addrs = resolver.resolve("router", None).unwrap();
+        assert_eq!(addrs.len(), 2);
+        let addrs = resolver.resolve("router", Some(Version::V6)).unwrap();
+        assert_eq!(addrs, vec!["[fd00::1]:0".parse().unwrap()]);
+        assert_eq!(
+            resolver.resolve("192.0.2.1", Some(Version::V6)),
+            Err(ResolveError::NoAddress)
+        );
+        assert!(resolver.resolve("switch", None).is_err());
+    }
+
+    #[test]
+    fn interleave_families() {
+        let addrs = ["10.0.0.1:0", "10.0.0.2:0", "[fd00::1]:0"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+        let addrs: Vec<_> = interleave(addrs)
+            .iter()
+            .map(|addr| addr.ip().to_string())
+            .collect();
+        assert_eq!(addrs, ["fd00::1", "10.0.0.1", "10.0.0.2"]);
+    }
+}
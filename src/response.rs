@@ -0,0 +1,505 @@
+use std::{convert::TryInto, fmt, net::Ipv6Addr, time::Duration};
+
+use crate::{
+    checksum, checksum_v6, DecodeError, IpHeader, Ipv4Header, Ipv6Header, Message, Timestamps,
+    Version, ICMP6_DST_UNREACH, ICMP6_ECHO_REQUEST, ICMP6_PACKET_TOO_BIG, ICMP6_PARAM_PROB,
+    ICMP_DEST_UNREACH, ICMP_ECHO, ICMP_PARAMETERPROB, ICMP_TIMESTAMP, ICMP_TIMESTAMPREPLY,
+    ICMP_TIME_EXCEEDED, TIMESTAMPLEN,
+};
+
+/// A received ICMP message borrowed from the receive buffer, see
+/// `Icmp::recv_into`. `Response` is its owned counterpart.
+#[derive(Clone, Copy)]
+pub struct ResponseRef<'a> {
+    ver: Version,
+    ttl: u8,
+    msg: &'a [u8],
+    ip: IpBytes<'a>,
+    rtt: Option<Duration>,
+}
+
+/// The IP header of a `ResponseRef`, empty if unknown. IPv6 headers are
+/// rebuilt rather than received, so they are held by value.
+#[derive(Clone, Copy)]
+enum IpBytes<'a> {
+    Borrowed(&'a [u8]),
+    V6([u8; 40]),
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<'a> ResponseRef<'a> {
+    /// Decodes an IPv4 packet carrying an ICMP message, as delivered by raw
+    /// sockets. The kernel does not verify the checksum before handing the
+    /// packet over, so it is checked here.
+    pub fn decode(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        if bytes.len() < 20 {
+            return Err(DecodeError::Truncated);
+        }
+        if bytes[0] >> 4 != 4 {
+            return Err(DecodeError::UnsupportedVersion(bytes[0] >> 4));
+        }
+        let ihl = bytes[0] & 0xf;
+        if ihl < 5 {
+            return Err(DecodeError::BadIhl(ihl));
+        }
+        let ip_hdr_len = 4 * ihl as usize;
+        if bytes.len() < ip_hdr_len {
+            return Err(DecodeError::Truncated);
+        }
+
+        let mut resp = Self::decode_icmp(Version::V4, &bytes[ip_hdr_len..], bytes[8])?;
+        resp.ip = IpBytes::Borrowed(&bytes[..ip_hdr_len]);
+        if !resp.verify_checksum() {
+            return Err(DecodeError::BadChecksum);
+        }
+        Ok(resp)
+    }
+
+    /// Decodes a bare ICMP message, as delivered by ICMPv6 raw sockets which
+    /// carry no IP header. The hop limit comes from ancillary data.
+    pub fn decode_icmp(ver: Version, bytes: &'a [u8], ttl: u8) -> Result<Self, DecodeError> {
+        if bytes.len() < 8 {
+            return Err(DecodeError::Truncated);
+        }
+        Ok(Self {
+            ver,
+            ttl,
+            msg: bytes,
+            ip: IpBytes::Borrowed(&[]),
+            rtt: None,
+        })
+    }
+
+    pub fn into_owned(self) -> Response {
+        Response {
+            ver: self.ver,
+            ttl: self.ttl,
+            msg: self.msg.into(),
+            ip: self.ip_bytes().into(),
+            rtt: self.rtt,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.msg.len()
+    }
+
+    #[inline]
+    pub fn ttl(&self) -> u8 {
+        self.ttl
+    }
+
+    #[inline]
+    pub fn kind(&self) -> u8 {
+        self.msg[0]
+    }
+
+    #[inline]
+    pub fn code(&self) -> u8 {
+        self.msg[1]
+    }
+
+    #[inline]
+    pub fn version(&self) -> Version {
+        self.ver
+    }
+
+    /// The typed message kind, combining `kind` and `code`.
+    #[inline]
+    pub fn message(&self) -> Message {
+        Message::from_wire(self.ver, self.kind(), self.code())
+    }
+
+    #[inline]
+    pub fn checksum(&self) -> u16 {
+        u16::from_be_bytes(self.msg[2..4].try_into().unwrap())
+    }
+
+    #[inline]
+    pub fn ident(&self) -> u16 {
+        u16::from_be_bytes(self.msg[4..6].try_into().unwrap())
+    }
+
+    #[inline]
+    pub fn sequence(&self) -> u16 {
+        u16::from_be_bytes(self.msg[6..8].try_into().unwrap())
+    }
+
+    #[inline]
+    pub fn data(&self) -> &'a [u8] {
+        &self.msg[8..]
+    }
+
+    /// The whole ICMP message as received.
+    #[inline]
+    pub fn bytes(&self) -> &'a [u8] {
+        self.msg
+    }
+
+    /// Verifies the checksum of an ICMPv4 message. The ICMPv6 checksum covers
+    /// a pseudo-header, see `verify_checksum_v6`.
+    pub fn verify_checksum(&self) -> bool {
+        checksum(self.msg) == 0
+    }
+
+    /// Verifies the checksum of an ICMPv6 message sent from `src` to `dst`.
+    pub fn verify_checksum_v6(&self, src: &Ipv6Addr, dst: &Ipv6Addr) -> bool {
+        checksum_v6(src, dst, self.msg) == 0
+    }
+
+    /// Identifier and sequence of the echo request quoted by an ICMP error
+    /// message such as Time Exceeded, if that is what the message carries.
+    pub fn quoted_echo(&self) -> Option<(u16, u16)> {
+        let dat = self.data();
+        let (hdr_len, proto, echo) = match *dat.first()? >> 4 {
+            4 if matches!(
+                self.kind(),
+                ICMP_DEST_UNREACH | ICMP_TIME_EXCEEDED | ICMP_PARAMETERPROB
+            ) =>
+            {
+                (
+                    4 * (dat[0] & 0xf) as usize,
+                    *dat.get(9)? as i32 == libc::IPPROTO_ICMP,
+                    ICMP_ECHO,
+                )
+            }
+            6 if matches!(self.kind(), ICMP6_DST_UNREACH..=ICMP6_PARAM_PROB) => (
+                40,
+                *dat.get(6)? as i32 == libc::IPPROTO_ICMPV6,
+                ICMP6_ECHO_REQUEST,
+            ),
+            _ => return None,
+        };
+        let icmp = dat.get(hdr_len..hdr_len + 8)?;
+        if !proto || icmp[0] != echo {
+            return None;
+        }
+        let idt = u16::from_be_bytes(icmp[4..6].try_into().unwrap());
+        let seq = u16::from_be_bytes(icmp[6..8].try_into().unwrap());
+        Some((idt, seq))
+    }
+
+    /// The next-hop MTU advertised by an ICMPv4 Fragmentation Needed or an
+    /// ICMPv6 Packet Too Big message. Routers predating RFC 1191 advertise 0.
+    pub fn next_hop_mtu(&self) -> Option<u32> {
+        match (self.ver, self.kind(), self.code()) {
+            (Version::V4, ICMP_DEST_UNREACH, 4) => {
+                Some(u16::from_be_bytes(self.msg[6..8].try_into().unwrap()) as u32)
+            }
+            (Version::V6, ICMP6_PACKET_TOO_BIG, _) => {
+                Some(u32::from_be_bytes(self.msg[4..8].try_into().unwrap()))
+            }
+            _ => None,
+        }
+    }
+
+    /// The timestamps of an ICMPv4 Timestamp or Timestamp Reply message.
+    pub fn timestamps(&self) -> Option<Timestamps> {
+        match (self.ver, self.kind(), self.code()) {
+            (Version::V4, ICMP_TIMESTAMP..=ICMP_TIMESTAMPREPLY, 0) => {
+                Timestamps::parse(self.data())
+            }
+            _ => None,
+        }
+    }
+
+    /// The IP header the message arrived with. Only raw IPv4 sockets receive
+    /// it; for IPv6 it is rebuilt from ancillary data, see `Ipv6Header`.
+    pub fn ip_header(&self) -> Option<IpHeader> {
+        let bytes = self.ip_bytes();
+        if bytes.is_empty() {
+            return None;
+        }
+        match self.ver {
+            Version::V4 => Ipv4Header::parse(bytes).ok().map(IpHeader::V4),
+            Version::V6 => Ipv6Header::parse(bytes).ok().map(IpHeader::V6),
+        }
+    }
+
+    fn ip_bytes(&self) -> &[u8] {
+        match &self.ip {
+            IpBytes::Borrowed(bytes) => bytes,
+            IpBytes::V6(bytes) => bytes,
+        }
+    }
+
+    /// Rebuilds the IPv6 header from the source address and the traffic
+    /// class, hop limit and destination given as ancillary data.
+    pub(crate) fn set_ipv6_header(&mut self, src: &Ipv6Addr, dst: &Ipv6Addr, tclass: u8) {
+        self.ip = IpBytes::V6(Ipv6Header::synthesize(
+            tclass,
+            self.msg.len(),
+            self.ttl,
+            src,
+            dst,
+        ));
+    }
+
+    /// Round-trip time, available when the request carried a timestamp.
+    #[inline]
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// Computes the round-trip time from the timestamp `send` wrote into the
+    /// echoed data, given the monotonic time the reply was received at.
+    pub(crate) fn stamp_rtt(&mut self, rcvd: Duration) {
+        let dat = self.data();
+        if dat.len() < TIMESTAMPLEN {
+            return;
+        }
+        let sec = u64::from_be_bytes(dat[..8].try_into().unwrap());
+        let nsec = u64::from_be_bytes(dat[8..16].try_into().unwrap());
+        let sent = Duration::new(sec, (nsec % 1_000_000_000) as u32);
+        self.rtt = rcvd.checked_sub(sent);
+    }
+
+    fn fmt_fields(&self, name: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct(name)
+            .field("ver", &self.ver)
+            .field("ttl", &self.ttl)
+            .field("typ", &self.kind())
+            .field("cod", &self.code())
+            .field("sum", &self.checksum())
+            .field("idt", &self.ident())
+            .field("seq", &self.sequence())
+            .field("dat", &self.data())
+            .field("rtt", &self.rtt)
+            .finish()
+    }
+}
+
+impl fmt::Debug for ResponseRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_fields("ResponseRef", f)
+    }
+}
+
+/// A received ICMP message. The accessors are those of `ResponseRef`.
+#[derive(Clone)]
+pub struct Response {
+    ver: Version,
+    ttl: u8,
+    msg: Box<[u8]>,
+    ip: Box<[u8]>,
+    rtt: Option<Duration>,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl Response {
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        ResponseRef::decode(bytes).map(ResponseRef::into_owned)
+    }
+
+    pub fn decode_icmp(ver: Version, bytes: &[u8], ttl: u8) -> Result<Self, DecodeError> {
+        ResponseRef::decode_icmp(ver, bytes, ttl).map(ResponseRef::into_owned)
+    }
+
+    #[inline]
+    pub fn view(&self) -> ResponseRef<'_> {
+        ResponseRef {
+            ver: self.ver,
+            ttl: self.ttl,
+            msg: &self.msg,
+            ip: IpBytes::Borrowed(&self.ip),
+            rtt: self.rtt,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.msg.len()
+    }
+
+    #[inline]
+    pub fn ttl(&self) -> u8 {
+        self.ttl
+    }
+
+    #[inline]
+    pub fn kind(&self) -> u8 {
+        self.view().kind()
+    }
+
+    #[inline]
+    pub fn code(&self) -> u8 {
+        self.view().code()
+    }
+
+    #[inline]
+    pub fn version(&self) -> Version {
+        self.ver
+    }
+
+    #[inline]
+    pub fn message(&self) -> Message {
+        self.view().message()
+    }
+
+    #[inline]
+    pub fn checksum(&self) -> u16 {
+        self.view().checksum()
+    }
+
+    #[inline]
+    pub fn ident(&self) -> u16 {
+        self.view().ident()
+    }
+
+    #[inline]
+    pub fn sequence(&self) -> u16 {
+        self.view().sequence()
+    }
+
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.msg[8..]
+    }
+
+    #[inline]
+    pub fn bytes(&self) -> &[u8] {
+        &self.msg
+    }
+
+    pub fn verify_checksum(&self) -> bool {
+        self.view().verify_checksum()
+    }
+
+    pub fn verify_checksum_v6(&self, src: &Ipv6Addr, dst: &Ipv6Addr) -> bool {
+        self.view().verify_checksum_v6(src, dst)
+    }
+
+    pub fn quoted_echo(&self) -> Option<(u16, u16)> {
+        self.view().quoted_echo()
+    }
+
+    pub fn next_hop_mtu(&self) -> Option<u32> {
+        self.view().next_hop_mtu()
+    }
+
+    pub fn timestamps(&self) -> Option<Timestamps> {
+        self.view().timestamps()
+    }
+
+    pub fn ip_header(&self) -> Option<IpHeader> {
+        self.view().ip_header()
+    }
+
+    #[inline]
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+}
+
+impl fmt::Debug for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.view().fmt_fields("Response", f)
+    }
+}
+
+impl From<ResponseRef<'_>> for Response {
+    fn from(resp: ResponseRef<'_>) -> Self {
+        resp.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Icmpv4Type, ICMP_ECHOREPLY};
+
+    fn echo_reply(ttl: u8, dat: &[u8]) -> Vec<u8> {
+        let mut icmp = vec![ICMP_ECHOREPLY, 0, 0, 0, 0, 99, 0, 1];
+        icmp.extend_from_slice(dat);
+        let sum = checksum(&icmp).to_be_bytes();
+        icmp[2..4].copy_from_slice(&sum);
+
+        let mut pkt = vec![0x45, 0, 0, 0, 0, 0, 0, 0, ttl, 1, 0, 0];
+        pkt.extend_from_slice(&[127, 0, 0, 1, 127, 0, 0, 1]);
+        pkt.extend_from_slice(&icmp);
+        pkt
+    }
+
+    #[test]
+    fn decode_echo_reply() {
+        let resp = Response::decode(&echo_reply(64, &[1, 2, 3, 4])).unwrap();
+        assert_eq!(resp.ttl(), 64);
+        assert_eq!(resp.kind(), ICMP_ECHOREPLY);
+        assert_eq!(resp.message(), Message::V4(Icmpv4Type::EchoReply));
+        assert_eq!(resp.ident(), 99);
+        assert_eq!(resp.sequence(), 1);
+        assert_eq!(resp.data(), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn decode_malformed() {
+        let pkt = echo_reply(64, &[1, 2, 3, 4]);
+        assert_eq!(
+            Response::decode(&pkt[..10]).unwrap_err(),
+            DecodeError::Truncated
+        );
+        assert_eq!(
+            Response::decode(&pkt[..24]).unwrap_err(),
+            DecodeError::Truncated
+        );
+        assert_eq!(
+            Response::decode_icmp(Version::V4, &pkt[20..26], 64).unwrap_err(),
+            DecodeError::Truncated
+        );
+
+        let mut bad = pkt.clone();
+        bad[0] = 0x43;
+        assert_eq!(Response::decode(&bad).unwrap_err(), DecodeError::BadIhl(3));
+        bad[0] = 0x65;
+        assert_eq!(
+            Response::decode(&bad).unwrap_err(),
+            DecodeError::UnsupportedVersion(6)
+        );
+
+        let mut bad = pkt;
+        bad[30] ^= 0xff;
+        assert_eq!(
+            Response::decode(&bad).unwrap_err(),
+            DecodeError::BadChecksum
+        );
+    }
+
+    #[test]
+    fn quoted_echo() {
+        let mut quote = vec![0x45, 0, 0, 28, 0, 0, 0, 0, 1, 1, 0, 0];
+        quote.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        quote.extend_from_slice(&[ICMP_ECHO, 0, 0, 0, 0, 77, 0, 5]);
+        let mut msg = vec![ICMP_TIME_EXCEEDED, 0, 0, 0, 0, 0, 0, 0];
+        msg.extend_from_slice(&quote);
+        let resp = Response::decode_icmp(Version::V4, &msg, 64).unwrap();
+        assert_eq!(resp.quoted_echo(), Some((77, 5)));
+
+        msg[0] = ICMP_ECHOREPLY;
+        let resp = Response::decode_icmp(Version::V4, &msg, 64).unwrap();
+        assert_eq!(resp.quoted_echo(), None);
+
+        msg[0] = ICMP_TIME_EXCEEDED;
+        let resp = Response::decode_icmp(Version::V4, &msg[..32], 64).unwrap();
+        assert_eq!(resp.quoted_echo(), None);
+    }
+
+    #[test]
+    fn next_hop_mtu() {
+        let pkt = crate::IcmpPacketBuilder::dest_unreachable(
+            crate::UnreachableCode::FragmentationNeeded,
+            &[0x45; 28],
+        )
+        .rest(1400)
+        .build();
+        let resp = Response::decode_icmp(Version::V4, &pkt, 64).unwrap();
+        assert_eq!(resp.next_hop_mtu(), Some(1400));
+
+        let pkt = crate::IcmpPacketBuilder::new(crate::Icmpv6Type::PacketTooBig)
+            .rest(1280)
+            .build();
+        let resp = Response::decode_icmp(Version::V6, &pkt, 64).unwrap();
+        assert_eq!(resp.next_hop_mtu(), Some(1280));
+        assert_eq!(resp.quoted_echo(), None);
+    }
+}
@@ -0,0 +1,113 @@
+/// How a reply relates to the requests sent before it, see `SeqTracker`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqStatus {
+    /// The first reply to a request no later request has been answered
+    /// before.
+    OnTime,
+    /// A reply to a request answered before.
+    Duplicate,
+    /// The first reply to a request, arriving after replies to later ones.
+    OutOfOrder,
+    /// A reply to a request that left the window or was never sent, such as
+    /// one from before the sequence wrapped around.
+    Stale,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Slot {
+    seq: u16,
+    sent: bool,
+    acked: bool,
+}
+
+/// Classifies replies by sequence over a sliding window of the most recent
+/// requests, telling replies to requests from before a wraparound apart from
+/// those to the current ones.
+#[derive(Debug, Clone)]
+pub struct SeqTracker {
+    slots: Box<[Slot]>,
+    next: u16,
+    newest: Option<u16>,
+}
+
+impl SeqTracker {
+    /// Tracks the last `window` requests. Panics unless `window` is between 1
+    /// and 32768, so that older and newer sequences compare unambiguously.
+    pub fn new(window: u16) -> Self {
+        assert!(
+            (1..=0x8000).contains(&(window as u32)),
+            "window must be between 1 and 32768"
+        );
+        Self {
+            slots: vec![Slot::default(); window as usize].into_boxed_slice(),
+            next: 0,
+            newest: None,
+        }
+    }
+
+    /// Records that the request with `seq` was sent. Sequences are expected
+    /// to be sent in order, wrapping from 65535 to 0.
+    pub fn sent(&mut self, seq: u16) {
+        let window = self.slots.len();
+        self.slots[seq as usize % window] = Slot {
+            seq,
+            sent: true,
+            acked: false,
+        };
+        self.next = seq.wrapping_add(1);
+        if self
+            .newest
+            .is_some_and(|newest| self.next.wrapping_sub(newest) as usize > window)
+        {
+            self.newest = None;
+        }
+    }
+
+    /// Classifies a reply with `seq`, recording it as answered.
+    pub fn classify(&mut self, seq: u16) -> SeqStatus {
+        let window = self.slots.len();
+        let age = self.next.wrapping_sub(seq) as usize;
+        let slot = &mut self.slots[seq as usize % window];
+        if age == 0 || age > window || !slot.sent || slot.seq != seq {
+            return SeqStatus::Stale;
+        }
+        if slot.acked {
+            return SeqStatus::Duplicate;
+        }
+        slot.acked = true;
+
+        match self.newest {
+            Some(newest) if (newest.wrapping_sub(seq) as i16) > 0 => SeqStatus::OutOfOrder,
+            _ => {
+                self.newest = Some(seq);
+                SeqStatus::OnTime
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_across_wrap() {
+        let mut tracker = SeqTracker::new(4);
+        for seq in [65534, 65535, 0] {
+            tracker.sent(seq);
+        }
+        assert_eq!(tracker.classify(65535), SeqStatus::OnTime);
+        assert_eq!(tracker.classify(65535), SeqStatus::Duplicate);
+        assert_eq!(tracker.classify(65534), SeqStatus::OutOfOrder);
+        assert_eq!(tracker.classify(0), SeqStatus::OnTime);
+        assert_eq!(tracker.classify(1), SeqStatus::Stale);
+
+        for seq in 1..4 {
+            tracker.sent(seq);
+        }
+        // 65535 left the window, and its slot now holds 3.
+        assert_eq!(tracker.classify(65535), SeqStatus::Stale);
+        assert_eq!(tracker.classify(3), SeqStatus::OnTime);
+        assert_eq!(tracker.classify(1), SeqStatus::OutOfOrder);
+    }
+}
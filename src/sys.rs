@@ -0,0 +1,275 @@
+use std::{
+    io,
+    mem::{size_of, size_of_val, zeroed, MaybeUninit},
+    net::{Ipv6Addr, SocketAddr},
+    os::unix::io::AsRawFd,
+    ptr,
+    time::Duration,
+};
+
+use socket2::{Domain, SockAddr, Socket, Type};
+
+pub(crate) fn setsockopt<T>(
+    sock: &Socket,
+    level: libc::c_int,
+    name: libc::c_int,
+    val: T,
+) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            level,
+            name,
+            &val as *const T as *const libc::c_void,
+            size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Ancillary {
+    pub ttl: Option<u8>,
+    pub stamp: Option<Duration>,
+    pub tclass: Option<u8>,
+    pub dst: Option<Ipv6Addr>,
+}
+
+pub(crate) fn getsockopt<T: Copy>(
+    sock: &Socket,
+    level: libc::c_int,
+    name: libc::c_int,
+) -> io::Result<T> {
+    let mut val = MaybeUninit::<T>::zeroed();
+    let mut len = size_of::<T>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            sock.as_raw_fd(),
+            level,
+            name,
+            val.as_mut_ptr().cast(),
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { val.assume_init() })
+}
+
+pub(crate) fn recv_msg(
+    sock: &Socket,
+    buf: &mut [MaybeUninit<u8>],
+) -> io::Result<(usize, SockAddr, Ancillary)> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let mut cbuf = [0u64; 32];
+    let mut anc = Ancillary::default();
+
+    let (len, addr) = unsafe {
+        SockAddr::init(|addr, addrlen| {
+            let mut msg: libc::msghdr = zeroed();
+            msg.msg_name = addr.cast();
+            msg.msg_namelen = *addrlen;
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = cbuf.as_mut_ptr().cast();
+            msg.msg_controllen = size_of_val(&cbuf) as _;
+
+            let len = libc::recvmsg(sock.as_raw_fd(), &mut msg, 0);
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            *addrlen = msg.msg_namelen;
+
+            anc = ancillary(&msg);
+            Ok(len as usize)
+        })
+    }?;
+
+    Ok((len, addr, anc))
+}
+
+/// Receives up to one message per buffer in a single `recvmmsg` call. Waits
+/// for the first message only, returning whatever else is already queued.
+pub(crate) fn recv_mmsg(
+    sock: &Socket,
+    bufs: &mut [&mut [u8]],
+) -> io::Result<Vec<(usize, SockAddr, Ancillary)>> {
+    let n = bufs.len();
+    let mut iovs: Vec<libc::iovec> = bufs
+        .iter_mut()
+        .map(|buf| libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        })
+        .collect();
+    let mut addrs: Vec<libc::sockaddr_storage> = vec![unsafe { zeroed() }; n];
+    let mut cbufs = vec![[0u64; 32]; n];
+    let mut msgs: Vec<libc::mmsghdr> = (0..n)
+        .map(|i| unsafe {
+            let mut msg: libc::mmsghdr = zeroed();
+            msg.msg_hdr.msg_name = addrs.as_mut_ptr().add(i).cast();
+            msg.msg_hdr.msg_namelen = size_of::<libc::sockaddr_storage>() as _;
+            msg.msg_hdr.msg_iov = iovs.as_mut_ptr().add(i);
+            msg.msg_hdr.msg_iovlen = 1;
+            msg.msg_hdr.msg_control = cbufs[i].as_mut_ptr().cast();
+            msg.msg_hdr.msg_controllen = size_of_val(&cbufs[i]) as _;
+            msg
+        })
+        .collect();
+
+    let ret = unsafe {
+        libc::recvmmsg(
+            sock.as_raw_fd(),
+            msgs.as_mut_ptr(),
+            n as _,
+            libc::MSG_WAITFORONE,
+            ptr::null_mut(),
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(msgs[..ret as usize]
+        .iter()
+        .zip(&addrs)
+        .map(|(msg, addr)| unsafe {
+            let anc = ancillary(&msg.msg_hdr);
+            let addr = SockAddr::new(*addr, msg.msg_hdr.msg_namelen);
+            (msg.msg_len as usize, addr, anc)
+        })
+        .collect())
+}
+
+/// Sends every packet to its destination with as few `sendmmsg` calls as
+/// possible. Packets the kernel refuses are skipped, and their errors returned
+/// with their index, so that one bad destination does not hold up the rest.
+pub(crate) fn send_mmsg(sock: &Socket, pkts: &[(&[u8], &SockAddr)]) -> Vec<(usize, io::Error)> {
+    let mut iovs: Vec<libc::iovec> = pkts
+        .iter()
+        .map(|(buf, _)| libc::iovec {
+            iov_base: buf.as_ptr() as *mut _,
+            iov_len: buf.len(),
+        })
+        .collect();
+    let mut msgs: Vec<libc::mmsghdr> = pkts
+        .iter()
+        .enumerate()
+        .map(|(i, (_, addr))| unsafe {
+            let mut msg: libc::mmsghdr = zeroed();
+            msg.msg_hdr.msg_name = addr.as_ptr() as *mut _;
+            msg.msg_hdr.msg_namelen = addr.len();
+            msg.msg_hdr.msg_iov = iovs.as_mut_ptr().add(i);
+            msg.msg_hdr.msg_iovlen = 1;
+            msg
+        })
+        .collect();
+
+    let mut errs = Vec::new();
+    let mut sent = 0;
+    while sent < msgs.len() {
+        let ret = unsafe {
+            libc::sendmmsg(
+                sock.as_raw_fd(),
+                msgs[sent..].as_mut_ptr(),
+                (msgs.len() - sent) as _,
+                0,
+            )
+        };
+        // sendmmsg stops at a failing packet without reporting its error, so
+        // the error only surfaces when the next call starts with that packet.
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                errs.push((sent, err));
+                sent += 1;
+            }
+            continue;
+        }
+        sent += ret as usize;
+    }
+    errs
+}
+
+unsafe fn ancillary(msg: &libc::msghdr) -> Ancillary {
+    let mut anc = Ancillary::default();
+    let mut cmsg = libc::CMSG_FIRSTHDR(msg);
+    while !cmsg.is_null() {
+        let (level, typ) = ((*cmsg).cmsg_level, (*cmsg).cmsg_type);
+        if (level == libc::IPPROTO_IP && typ == libc::IP_TTL)
+            || (level == libc::IPPROTO_IPV6 && typ == libc::IPV6_HOPLIMIT)
+        {
+            let ttl: libc::c_int = ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast());
+            anc.ttl = Some(ttl as u8);
+        } else if level == libc::SOL_SOCKET && typ == libc::SCM_TIMESTAMPNS {
+            let ts: libc::timespec = ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast());
+            anc.stamp = Some(timespec(ts));
+        } else if level == libc::IPPROTO_IPV6 && typ == libc::IPV6_TCLASS {
+            let tclass: libc::c_int = ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast());
+            anc.tclass = Some(tclass as u8);
+        } else if level == libc::IPPROTO_IPV6 && typ == libc::IPV6_PKTINFO {
+            let info: libc::in6_pktinfo = ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast());
+            anc.dst = Some(info.ipi6_addr.s6_addr.into());
+        }
+        cmsg = libc::CMSG_NXTHDR(msg, cmsg);
+    }
+    anc
+}
+
+/// Waits until the socket is readable, returning `false` on timeout.
+pub(crate) fn poll_read(sock: &Socket, timeout: Duration) -> io::Result<bool> {
+    let mut pfd = libc::pollfd {
+        fd: sock.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // Round up so that a sub-millisecond remainder does not spin.
+    let ms = timeout.as_nanos().div_ceil(1_000_000);
+    let ms = ms.min(libc::c_int::MAX as u128) as libc::c_int;
+
+    loop {
+        match unsafe { libc::poll(&mut pfd, 1, ms) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            n => return Ok(n > 0),
+        }
+    }
+}
+
+pub(crate) fn monotonic() -> Duration {
+    clock(libc::CLOCK_MONOTONIC)
+}
+
+pub(crate) fn realtime() -> Duration {
+    clock(libc::CLOCK_REALTIME)
+}
+
+fn clock(id: libc::clockid_t) -> Duration {
+    let mut ts: libc::timespec = unsafe { zeroed() };
+    unsafe { libc::clock_gettime(id, &mut ts) };
+    timespec(ts)
+}
+
+fn timespec(ts: libc::timespec) -> Duration {
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+/// Checks that the kernel has a route to `addr` by connecting a UDP socket,
+/// which sends nothing.
+pub(crate) fn route_exists(addr: &SocketAddr) -> io::Result<()> {
+    let mut addr = *addr;
+    addr.set_port(9);
+    let sock = Socket::new(Domain::for_address(addr), Type::DGRAM, None)?;
+    sock.connect(&addr.into())
+}
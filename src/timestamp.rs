@@ -0,0 +1,140 @@
+use std::{convert::TryInto, time::Duration};
+
+use socket2::SockAddr;
+
+use crate::Response;
+
+/// Milliseconds in a day, the range of ICMP timestamps.
+const DAY: i64 = 86_400_000;
+
+/// The timestamps of an ICMP Timestamp or Timestamp Reply message, in
+/// milliseconds since midnight UTC (RFC 792).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamps {
+    pub originate: u32,
+    pub receive: u32,
+    pub transmit: u32,
+}
+
+impl Timestamps {
+    pub(crate) fn parse(dat: &[u8]) -> Option<Self> {
+        let dat = dat.get(..12)?;
+        let field = |i: usize| u32::from_be_bytes(dat[i..i + 4].try_into().unwrap());
+        Some(Self {
+            originate: field(0),
+            receive: field(4),
+            transmit: field(8),
+        })
+    }
+
+    /// Hosts that cannot provide milliseconds since midnight UTC set the high
+    /// bit and put some other time in the rest, which the estimates of
+    /// `TimestampReply` are meaningless for.
+    pub fn is_standard(&self) -> bool {
+        (self.originate | self.receive | self.transmit) & 0x8000_0000 == 0
+    }
+
+    /// Time the peer held the request, between receiving and answering it.
+    pub fn turnaround(&self) -> Duration {
+        Duration::from_millis(diff(self.transmit, self.receive).max(0) as u64)
+    }
+}
+
+/// A Timestamp Reply together with the local time it arrived at, from which
+/// delay and clock offset are estimated as NTP does. All figures are in
+/// milliseconds and account for the timestamps wrapping at midnight.
+#[derive(Debug)]
+pub struct TimestampReply {
+    pub len: usize,
+    pub addr: SockAddr,
+    pub resp: Response,
+    pub timestamps: Timestamps,
+    pub arrived: u32,
+}
+
+impl TimestampReply {
+    /// Round-trip time, excluding the time the peer held the request.
+    pub fn rtt(&self) -> i64 {
+        let ts = &self.timestamps;
+        diff(self.arrived, ts.originate) - diff(ts.transmit, ts.receive)
+    }
+
+    /// Time from us to the peer as the clocks tell it, the real delay plus
+    /// the `offset`.
+    pub fn outbound(&self) -> i64 {
+        diff(self.timestamps.receive, self.timestamps.originate)
+    }
+
+    /// Time from the peer back to us as the clocks tell it, the real delay
+    /// minus the `offset`.
+    pub fn inbound(&self) -> i64 {
+        diff(self.arrived, self.timestamps.transmit)
+    }
+
+    /// Estimated one-way delay, half the `rtt`, assuming a symmetric path.
+    pub fn delay(&self) -> i64 {
+        self.rtt() / 2
+    }
+
+    /// Estimated amount the peer clock is ahead of ours.
+    pub fn offset(&self) -> i64 {
+        (self.outbound() - self.inbound()) / 2
+    }
+}
+
+/// Current time in milliseconds since midnight UTC.
+pub(crate) fn since_midnight() -> u32 {
+    at_midnight_offset(crate::sys::realtime())
+}
+
+/// Milliseconds since midnight UTC of `time`, given since the Unix epoch.
+pub(crate) fn at_midnight_offset(time: Duration) -> u32 {
+    ((time.as_secs() % 86_400) * 1000 + time.subsec_millis() as u64) as u32
+}
+
+/// `a - b` modulo a day, as the shortest signed distance.
+fn diff(a: u32, b: u32) -> i64 {
+    let d = (a as i64 - b as i64).rem_euclid(DAY);
+    if d > DAY / 2 {
+        d - DAY
+    } else {
+        d
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_across_midnight() {
+        let reply = |originate, receive, transmit, arrived| TimestampReply {
+            len: 0,
+            addr: SockAddr::from(std::net::SocketAddr::from(([127, 0, 0, 1], 0))),
+            resp: Response::decode_icmp(crate::Version::V4, &[14, 0, 0, 0, 0, 0, 0, 0], 64)
+                .unwrap(),
+            timestamps: Timestamps {
+                originate,
+                receive,
+                transmit,
+            },
+            arrived,
+        };
+
+        // Peer 100 ms ahead, 10 ms each way, 2 ms turnaround.
+        let r = reply(1000, 1110, 1112, 1022);
+        assert_eq!((r.rtt(), r.delay(), r.offset()), (20, 10, 100));
+        assert_eq!((r.outbound(), r.inbound()), (110, -90));
+
+        // Same, with our clock about to wrap at midnight.
+        let o = (DAY - 5) as u32;
+        let r = reply(o, 105, 107, 17);
+        assert_eq!((r.rtt(), r.delay(), r.offset()), (20, 10, 100));
+    }
+
+    #[test]
+    fn realtime_to_midnight_offset() {
+        let time = Duration::new(3 * 86_400 + 3_600, 250_900_000);
+        assert_eq!(at_midnight_offset(time), 3_600_250);
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    fmt, io,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use crate::{invalid_input, Icmp, SocketKind};
+
+/// The answer to a single probe: who sent it, after how long, and which ICMP
+/// message it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Probe {
+    pub addr: IpAddr,
+    pub rtt: Duration,
+    pub kind: u8,
+    pub code: u8,
+}
+
+/// A hop of the path, with one entry per probe; `None` marks a probe that
+/// went unanswered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hop {
+    pub ttl: u8,
+    pub probes: Vec<Option<Probe>>,
+    /// Set when a probe was answered with anything but Time Exceeded, which
+    /// ends the trace.
+    pub reached: bool,
+}
+
+/// Formats a hop the way traceroute prints it.
+impl fmt::Display for Hop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:2}", self.ttl)?;
+        let mut last = None;
+        for probe in &self.probes {
+            match probe {
+                Some(probe) => {
+                    if last != Some(probe.addr) {
+                        write!(f, "  {}", probe.addr)?;
+                        last = Some(probe.addr);
+                    }
+                    write!(f, "  {:.3} ms", probe.rtt.as_secs_f64() * 1000.0)?;
+                }
+                None => write!(f, "  *")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Traces the path to the destination of an `Icmp` by sending echo requests
+/// with increasing TTL. Only raw sockets are supported: this implementation
+/// reads Time Exceeded errors off the socket, while Linux hands them to ping
+/// sockets through `IP_RECVERR` and the error queue instead.
+#[derive(Debug)]
+pub struct Traceroute {
+    icmp: Icmp,
+    /// The TTL the socket had, put back by `into_inner`.
+    ttl: u32,
+    first_ttl: u8,
+    max_hops: u8,
+    probes: usize,
+    timeout: Duration,
+}
+
+impl Traceroute {
+    pub fn new(icmp: Icmp) -> io::Result<Self> {
+        if icmp.kind != SocketKind::Raw {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "traceroute needs a raw socket",
+            ));
+        }
+        Ok(Self {
+            ttl: icmp.current_ttl()?,
+            icmp,
+            first_ttl: 1,
+            max_hops: 30,
+            probes: 3,
+            timeout: Duration::from_secs(5),
+        })
+    }
+
+    /// TTL of the first hop probed, 1 by default. It must not exceed
+    /// `max_hops`, so raise that first when going past 30.
+    pub fn first_ttl(mut self, ttl: u8) -> io::Result<Self> {
+        if ttl == 0 || ttl > self.max_hops {
+            return Err(invalid_input("first TTL must be between 1 and max hops"));
+        }
+        self.first_ttl = ttl;
+        Ok(self)
+    }
+
+    /// TTL of the last hop probed, 30 by default. It must not be below
+    /// `first_ttl`.
+    pub fn max_hops(mut self, hops: u8) -> io::Result<Self> {
+        if hops == 0 || hops < self.first_ttl {
+            return Err(invalid_input("max hops must be between first TTL and 255"));
+        }
+        self.max_hops = hops;
+        Ok(self)
+    }
+
+    /// Number of probes sent per hop.
+    pub fn probes(mut self, probes: usize) -> Self {
+        self.probes = probes;
+        self
+    }
+
+    /// How long to wait for the answer to each probe.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the `Icmp` with the TTL it had before the trace.
+    pub fn into_inner(self) -> io::Result<Icmp> {
+        self.icmp.set_ttl(self.ttl)?;
+        Ok(self.icmp)
+    }
+
+    /// Runs the trace, calling `on_hop` as each hop completes.
+    pub fn run<F: FnMut(&Hop)>(&mut self, mut on_hop: F) -> io::Result<Vec<Hop>> {
+        let mut hops = Vec::new();
+
+        for ttl in self.first_ttl..=self.max_hops {
+            self.icmp.set_ttl(ttl as u32)?;
+            let mut hop = Hop {
+                ttl,
+                probes: Vec::with_capacity(self.probes),
+                reached: false,
+            };
+            for _ in 0..self.probes {
+                let probe = self.probe()?;
+                hop.reached |=
+                    probe.is_some_and(|probe| probe.kind != self.icmp.ver.time_exceeded());
+                hop.probes.push(probe);
+            }
+
+            on_hop(&hop);
+            let reached = hop.reached;
+            hops.push(hop);
+            if reached {
+                break;
+            }
+        }
+        Ok(hops)
+    }
+
+    fn probe(&mut self) -> io::Result<Option<Probe>> {
+        let (ver, idt) = (self.icmp.ver, self.icmp.idt);
+        let sent = Instant::now();
+        let seq = self.icmp.send()?;
+
+        let res = self.icmp.recv_filter(Some(sent + self.timeout), |resp| {
+            if resp.kind() == ver.echo_reply() {
+                resp.ident() == idt && resp.sequence() == seq
+            } else {
+                resp.quoted_echo() == Some((idt, seq))
+            }
+        });
+        let rtt = sent.elapsed();
+        match res {
+            Ok((_, addr, resp)) => Ok(addr.as_socket().map(|addr| Probe {
+                addr: addr.ip(),
+                rtt,
+                kind: resp.kind(),
+                code: resp.code(),
+            })),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}